@@ -0,0 +1,60 @@
+use {
+    criterion::{criterion_group, criterion_main, Criterion, Throughput},
+    rayon::prelude::*,
+    raytracer::{
+        packet::{launch_packet, PACKET_SIZE},
+        scene::{extract_camera, extract_surfaces, extract_vops},
+        Ray,
+    },
+    serde_yaml::{from_str, Mapping, Value},
+    std::fs,
+};
+
+/// Camera density used for the benchmark, lower than the demo's own to keep iterations short.
+const DENSITY: f64 = 20.0;
+
+/// Load the demo scene with a reduced camera density.
+fn load_demo() -> Mapping {
+    let contents =
+        fs::read_to_string("examples/demo.yaml").expect("Something went wrong reading the file");
+    let mut document: Mapping = from_str(&contents).expect("Error in parsing the file");
+    document
+        .get_mut(&Value::String("camera".to_owned()))
+        .and_then(|camera| camera.as_mapping_mut())
+        .expect("No camera given.")
+        .insert(Value::String("density".to_owned()), Value::from(DENSITY));
+    document
+}
+
+/// Primary ray throughput on the demo scene, launching rays one by one or in packets.
+fn primary_rays(c: &mut Criterion) {
+    let document = load_demo();
+    let volumes = extract_vops(&document);
+    let camera = extract_camera(&document, &volumes);
+    let surfaces = extract_surfaces(&document, &volumes);
+    let rays: Vec<Ray> = camera.create_rays();
+
+    let mut group = c.benchmark_group("demo");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.sample_size(20);
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            rays.clone()
+                .into_par_iter()
+                .map(|mut r| r.launch(&surfaces, &camera.bounce_limit))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("packet", |b| {
+        b.iter(|| {
+            rays.clone()
+                .par_chunks_mut(PACKET_SIZE)
+                .flat_map_iter(|packet| launch_packet(packet, &surfaces, &camera.bounce_limit))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, primary_rays);
+criterion_main!(benches);
@@ -0,0 +1,280 @@
+use {
+    crate::{camera_path::CameraPath, motion::segment},
+    serde::Deserialize,
+    serde_yaml::{Mapping, Number, Value},
+    std::path::Path,
+};
+
+/// Sequence of frames rendered in one run, the scene changing between them along keyframe tracks.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Animation {
+    pub frames: usize,
+    pub fps: f64,
+    #[serde(default)]
+    pub tracks: Vec<Track>,
+    /// Path of the camera, placing it in every frame over the tracks.
+    pub camera_path: Option<CameraPath>,
+}
+
+/// How values change between two keyframes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Slow at both keyframes and fastest halfway.
+    Ease,
+}
+
+impl Interpolation {
+    fn ease(self, s: f64) -> f64 {
+        match self {
+            Interpolation::Linear => s,
+            Interpolation::Ease => s * s * (3.0 - 2.0 * s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrackKeyframe {
+    /// Time of the keyframe in seconds.
+    pub time: f64,
+    pub value: Value,
+}
+
+/// Value of the scene changing over time. The value is found by its path from the top of the
+/// scene, keys and list indices separated by dots, such as `camera.origin`, `surfaces.2.center`
+/// or `volumes.glass.ior`, and must be given in the scene.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Track {
+    pub path: String,
+    pub keyframes: Vec<TrackKeyframe>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+impl Track {
+    /// Value of the track at a given time. Numbers and lists of numbers are interpolated,
+    /// staying integers between integer keyframes; other values change at each keyframe.
+    pub fn value(&self, time: f64) -> Value {
+        let times: Vec<f64> = self.keyframes.iter().map(|k| k.time).collect();
+        let (a, b, s) = segment(&times, time);
+        let s = self.interpolation.ease(s);
+        interpolate(&self.keyframes[a].value, &self.keyframes[b].value, s)
+            .unwrap_or_else(|| self.keyframes[a].value.clone())
+    }
+}
+
+fn interpolate(a: &Value, b: &Value, s: f64) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => {
+            let value = x.as_f64()? + s * (y.as_f64()? - x.as_f64()?);
+            Some(match (x.as_i64(), y.as_i64()) {
+                (Some(_), Some(_)) => Value::Number(Number::from(value.round() as i64)),
+                _ => Value::Number(Number::from(value)),
+            })
+        }
+        (Value::Sequence(x), Value::Sequence(y)) if x.len() == y.len() => x
+            .iter()
+            .zip(y)
+            .map(|(x, y)| interpolate(x, y, s))
+            .collect::<Option<_>>()
+            .map(Value::Sequence),
+        _ => None,
+    }
+}
+
+impl Animation {
+    /// Check that every track and the camera path can be evaluated, and that every track path
+    /// leads to a value of the given scene.
+    pub fn validate(&self, document: &Mapping) {
+        if self.fps <= 0.0 {
+            panic!("Animation frame rate must be positive.")
+        }
+        if let Some(path) = &self.camera_path {
+            path.validate();
+        }
+        for track in &self.tracks {
+            let keys: Vec<&str> = track.path.split('.').collect();
+            if value_at(&mut Value::Mapping(document.clone()), &keys).is_none() {
+                panic!(
+                    "Animation track path {} not found in the scene.",
+                    track.path
+                )
+            }
+            if track.keyframes.is_empty() {
+                panic!("Animation track {} has no keyframes.", track.path)
+            }
+            if track
+                .keyframes
+                .windows(2)
+                .any(|pair| pair[1].time <= pair[0].time)
+            {
+                panic!(
+                    "Keyframes of animation track {} must be given in order of time.",
+                    track.path
+                )
+            }
+        }
+    }
+
+    /// Time of a frame in seconds, counting frames from 0.
+    pub fn time(&self, frame: usize) -> f64 {
+        frame as f64 / self.fps
+    }
+
+    /// Scene of a frame, at its time and with the values of all the tracks at that time.
+    pub fn frame_document(&self, document: &Mapping, frame: usize) -> Mapping {
+        let time = self.time(frame);
+        let mut document = document.clone();
+        document.insert(
+            Value::String("time".to_owned()),
+            Value::Number(Number::from(time)),
+        );
+        let mut document = Value::Mapping(document);
+        for track in &self.tracks {
+            let keys: Vec<&str> = track.path.split('.').collect();
+            *value_at(&mut document, &keys)
+                .expect("Animation track path not found in the scene.") = track.value(time);
+        }
+        let mut document = match document {
+            Value::Mapping(document) => document,
+            _ => unreachable!(),
+        };
+        if let Some(path) = &self.camera_path {
+            let camera = document
+                .get_mut(&Value::String("camera".to_owned()))
+                .and_then(Value::as_mapping_mut)
+                .expect("No camera given.");
+            path.apply(camera, path.fraction(frame, self.frames));
+        }
+        document
+    }
+}
+
+/// Value of the scene at the given keys, if they all exist.
+fn value_at<'a>(value: &'a mut Value, keys: &[&str]) -> Option<&'a mut Value> {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => return Some(value),
+    };
+    let child = match value {
+        Value::Mapping(mapping) => mapping.get_mut(&Value::String(key.to_string())),
+        Value::Sequence(sequence) => key
+            .parse::<usize>()
+            .ok()
+            .and_then(move |i| sequence.get_mut(i)),
+        _ => None,
+    }?;
+    value_at(child, rest)
+}
+
+/// Path of a frame image, `frame_0001` and so on next to the image path, with its extension.
+pub fn frame_path(filepath: &str, frame: usize) -> String {
+    let path = Path::new(filepath);
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("frame_{:04}.{}", frame + 1, extension),
+        None => format!("frame_{:04}", frame + 1),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::TOLERANCE, serde_yaml::from_str};
+
+    fn track(yaml: &str) -> Track {
+        from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn track_values() {
+        let linear =
+            track("{path: a, keyframes: [{time: 1, value: [0, 2.0]}, {time: 3, value: [4, 3.0]}]}");
+        assert_eq!(linear.value(0.0), from_str::<Value>("[0, 2.0]").unwrap());
+        assert_eq!(linear.value(1.5), from_str::<Value>("[1, 2.25]").unwrap());
+        assert_eq!(linear.value(9.0), from_str::<Value>("[4, 3.0]").unwrap());
+
+        let ease = track(
+            "{path: a, interpolation: ease, keyframes: [{time: 0, value: 0.0}, {time: 1, value: 1.0}]}",
+        );
+        assert!((ease.value(0.25).as_f64().unwrap() - 0.15625).abs() < TOLERANCE);
+        assert!((ease.value(0.5).as_f64().unwrap() - 0.5).abs() < TOLERANCE);
+
+        // values that cannot be interpolated change at the keyframes
+        let names = track("{path: a, keyframes: [{time: 0, value: air}, {time: 1, value: glass}]}");
+        assert_eq!(names.value(0.9), Value::String("air".to_owned()));
+        assert_eq!(names.value(1.0), Value::String("glass".to_owned()));
+    }
+
+    #[test]
+    fn frame_documents() {
+        let document: Mapping = from_str(
+            "{camera: {origin: [0, 0, 0], shutter: {close: 0.2}}, volumes: {glass: {ior: 1.5}}, \
+             surfaces: [{center: [0, 0, 0]}, {center: [1, 1, 1]}]}",
+        )
+        .unwrap();
+        let animation: Animation = from_str(
+            "{frames: 3, fps: 2, tracks: [\
+             {path: surfaces.1.center, keyframes: [{time: 0, value: [1, 1, 1]}, {time: 1, value: [1, 3, 1]}]}, \
+             {path: volumes.glass.ior, keyframes: [{time: 0, value: 1.5}, {time: 1, value: 1.7}]}, \
+             {path: camera.shutter, keyframes: [{time: 0, value: {close: 0.1}}]}]}",
+        )
+        .unwrap();
+        animation.validate(&document);
+        let frame = Value::Mapping(animation.frame_document(&document, 1));
+        let expected: Value = from_str(
+            "{camera: {origin: [0, 0, 0], shutter: {close: 0.1}}, volumes: {glass: {ior: 1.6}}, \
+             surfaces: [{center: [0, 0, 0]}, {center: [1, 2, 1]}]}",
+        )
+        .unwrap();
+        let ior = frame["volumes"]["glass"]["ior"].as_f64().unwrap();
+        assert!((ior - 1.6).abs() < TOLERANCE);
+        assert_eq!(frame["surfaces"], expected["surfaces"]);
+        assert_eq!(frame["camera"], expected["camera"]);
+
+        assert_eq!(frame_path("out/render.png", 0), "out/frame_0001.png");
+
+        // paths must lead to values of the scene
+        for path in &["camera.orgin", "surfaces.9.center", "volumes.glas.ior"] {
+            let animation: Animation = from_str(&format!(
+                "{{frames: 3, fps: 2, tracks: [{{path: {}, keyframes: [{{time: 0, value: 1}}]}}]}}",
+                path
+            ))
+            .unwrap();
+            let message = std::panic::catch_unwind(|| animation.validate(&document))
+                .unwrap_err()
+                .downcast::<String>()
+                .unwrap();
+            assert!(message.contains(path));
+        }
+    }
+
+    /// A moving surface must move between frames, the camera rays travelling at the time of their
+    /// frame.
+    #[test]
+    fn surfaces_move_between_frames() {
+        use crate::scene::{extract_camera, extract_surfaces, extract_vops};
+
+        let document: Mapping = from_str(
+            "{volumes: {air: {ior: 1, abs: [0, 0, 0]}}, \
+             camera: {origin: [0, 0, 0], gaze: [0, 1, 0], up: [0, 0, 1], fov: [1, 1], \
+             density: 1, vop: air, shutter: {close: 0.1}}, \
+             surfaces: [{type: sphere, center: [0, 10, 0], radius: 1, sop: dark, \
+             vop_above: air, vop_below: air, motion: {velocity: [4, 0, 0]}}]}",
+        )
+        .unwrap();
+        let animation: Animation = from_str("{frames: 2, fps: 1}").unwrap();
+        let hits = |frame| {
+            let document = animation.frame_document(&document, frame);
+            let volumes = extract_vops(&document);
+            let camera = extract_camera(&document, &volumes);
+            let surfaces = extract_surfaces(&document, &volumes);
+            let ray = &camera.create_rays()[0];
+            assert!(ray.time >= animation.time(frame) && ray.time < animation.time(frame) + 0.1);
+            surfaces[0].intersection(ray).is_some()
+        };
+        assert!(hits(0));
+        assert!(!hits(1));
+    }
+}
@@ -1,101 +1,449 @@
 use {
-    crate::{ray::BounceResult, Ray, Surface, VOP},
+    crate::{
+        lens::{Distortion, Lens, LensBuilder, Vignetting},
+        motion::Shutter,
+        packet::{launch_packet, PACKET_SIZE},
+        projection::{angular_direction, FisheyeMapping, Projection},
+        random::{Rng, Stream},
+        ray::{BounceLimit, BounceResult},
+        sampling::{Filter, Sample, Sampling},
+        stereo::{Eye, Stereo, StereoMode},
+        Ray, Surface, VOP,
+    },
     image::{Rgb, RgbImage},
     nalgebra::{Isometry3, Point3, Unit, Vector3},
     rayon::prelude::*,
     serde::Deserialize,
     std::collections::HashMap,
+    std::iter::Sum,
+    std::ops::Range,
+    std::sync::atomic::{AtomicUsize, Ordering},
     std::sync::Arc,
-    std::time::Instant,
+    std::time::{Duration, Instant},
 };
-// use indicatif::ProgressBar;
 
-/// Split a rectangle into dividing unit cells, according to a given spacing and return either
-/// the corners or the centers of the subdivisions.
-fn split_rectangle(
-    corner: Point3<f64>,
-    size_x: f64,
-    size_y: f64,
-    num_x: usize,
-    num_y: usize,
-    corners: bool,
-) -> Vec<Point3<f64>> {
-    let mut points: Vec<Point3<f64>> = Vec::new();
-    let mut v: Vector3<f64>;
-
-    // spacing between adjacent points on the grid
-    let dx: f64 = size_x / num_x as f64;
-    let dy: f64 = size_y / num_y as f64;
-
-    for i in 0..num_x {
-        for j in 0..num_y {
-            v = Vector3::new(i as f64 * dx, j as f64 * dy, 0.0);
-            // add half-spacing to obtain centers of subgrid
-            if !corners {
-                v.x += dx / 2.0;
-                v.y += dy / 2.0;
-            }
-            points.push(corner + v);
+/// Side of the square tiles the image is rendered in, in pixels.
+pub const TILE_SIZE: usize = 16;
+
+/// Snapshot of the state of a render, handed to progress callbacks.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub rays_done: usize,
+    pub rays_total: usize,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Fraction of the rays traced so far, between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        self.rays_done as f64 / self.rays_total.max(1) as f64
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays_done as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Estimated time left, assuming the current rate holds.
+    pub fn eta(&self) -> Duration {
+        if self.rays_done == 0 {
+            return Duration::from_secs(0);
         }
+        Duration::from_secs_f64((self.rays_total - self.rays_done) as f64 / self.rays_per_second())
+    }
+}
+
+/// Numbers of rays traced, of those stopped by the bounce limit and of those that met a surface
+/// from a medium other than the one given for that side.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RayCount {
+    pub traced: usize,
+    pub bounce_limited: usize,
+    pub medium_mismatches: usize,
+}
+
+impl RayCount {
+    pub fn add(&mut self, other: RayCount) {
+        self.traced += other.traced;
+        self.bounce_limited += other.bounce_limited;
+        self.medium_mismatches += other.medium_mismatches;
     }
-    points
 }
 
-/// Trace a number of rays through the given scene and return their final color values.
-pub fn trace_rays(rays: Vec<Ray>, scene: &[Arc<dyn Surface + Send + Sync>]) -> Vec<[u8; 3]> {
-    print!("Starting raytrace... ");
-    let num_rays: usize = rays.len();
+impl<'a> Sum<&'a RayCount> for RayCount {
+    fn sum<I: Iterator<Item = &'a RayCount>>(counts: I) -> Self {
+        let mut total = RayCount::default();
+        counts.for_each(|count| total.add(*count));
+        total
+    }
+}
+
+/// Trace the camera's rays through the given scene and return the color of every pixel. The image
+/// is rendered tile by tile in parallel, generating each tile's rays only when it is traced, so
+/// that memory use does not grow with the resolution.
+///
+/// `on_progress` is called from the worker threads every time a tile is finished. Workers only
+/// share an atomic counter, so the callback should be cheap and do its own synchronization.
+pub fn trace_rays<F>(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    on_progress: F,
+) -> Vec<[u8; 3]>
+where
+    F: Fn(&Progress) + Sync,
+{
+    println!("Starting raytrace...");
     let t0 = Instant::now();
 
-    let mut result: Vec<(usize, u8, u8, u8)> = rays
-        .into_par_iter()
-        .enumerate()
-        .map(|(i, mut r)| {
-            // pbar.inc(1);
-            match r.launch(scene) {
-                BounceResult::Count(r, g, b) => (i, r, g, b),
-                BounceResult::Kill => (i, 0, 0, 0),
-                _ => panic!("Something has gone wrong."),
-            }
-        })
+    let (pixels, count) = trace_pass(camera, scene, 0, on_progress);
+    let result: Vec<[u8; 3]> = pixels
+        .into_iter()
+        .map(|pixel| [pixel[0] as u8, pixel[1] as u8, pixel[2] as u8])
         .collect();
 
     let seconds = t0.elapsed().as_millis() as f64 / 1000.0;
-    // pbar.finish_and_clear();
-
-    // sort result by index
-    result.sort_by_key(|(i, _, _, _)| *i);
-    let result: Vec<[u8; 3]> = result.into_iter().map(|(_, r, g, b)| [r, g, b]).collect();
 
     // show total time
     println!(
-        "done! Total time: {}s, rays/s: {}.",
+        "Raytrace done! Total time: {}s, rays/s: {}, samples/pixel: {:.2}.",
         seconds,
-        (num_rays as f64 / seconds) as u64
+        (count.traced as f64 / seconds) as u64,
+        count.traced as f64 / camera.window.num_pixels() as f64
     );
+    if count.bounce_limited > 0 {
+        println!(
+            "{} ray(s) stopped after {} bounces.",
+            count.bounce_limited, camera.bounce_limit.max
+        );
+    }
+    if count.medium_mismatches > 0 {
+        println!(
+            "Warning: {} ray(s) met a surface from a medium other than the one given for that side.",
+            count.medium_mismatches
+        );
+    }
+    result
+}
+
+/// Trace a single pass over the image, with the samples of the given pass of a progressive render,
+/// and return the mean color of every pixel of the camera window, row by row, along
+/// with the numbers of rays traced. See `trace_rays`.
+///
+/// Progress is counted in nominal samples, `antialiasing`^2 per pixel, since the number of
+/// samples adaptive antialiasing will take is not known in advance.
+pub fn trace_pass<F>(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    pass: usize,
+    on_progress: F,
+) -> (Vec<[f64; 3]>, RayCount)
+where
+    F: Fn(&Progress) + Sync,
+{
+    let window = &camera.window;
+    let num_rays: usize = window.num_pixels() * camera.antialiasing.pow(2);
+    let rays_done = AtomicUsize::new(0);
+    let rays_traced = AtomicUsize::new(0);
+    let rays_limited = AtomicUsize::new(0);
+    let rays_mismatched = AtomicUsize::new(0);
+    let t0 = Instant::now();
+
+    // every band of rows is a contiguous slice of the output, split further into square tiles
+    let width = window.y.len();
+    let mut result: Vec<[f64; 3]> = vec![[0.0; 3]; window.num_pixels()];
     result
+        .par_chunks_mut(TILE_SIZE * width)
+        .enumerate()
+        .for_each(|(band, pixels)| {
+            let x_start = window.x.start + band * TILE_SIZE;
+            let x = x_start..x_start + pixels.len() / width;
+            for y_start in window.y.clone().step_by(TILE_SIZE) {
+                let y = y_start..(y_start + TILE_SIZE).min(window.y.end);
+                let (tile, count) = trace_tile(camera, scene, x.clone(), y.clone(), pass);
+                rays_traced.fetch_add(count.traced, Ordering::Relaxed);
+                rays_limited.fetch_add(count.bounce_limited, Ordering::Relaxed);
+                rays_mismatched.fetch_add(count.medium_mismatches, Ordering::Relaxed);
+                for (k, pixel) in tile.into_iter().enumerate() {
+                    pixels[(k / y.len()) * width + y.start - window.y.start + k % y.len()] = pixel;
+                }
+
+                let tile_rays = x.len() * y.len() * camera.antialiasing.pow(2);
+                on_progress(&Progress {
+                    rays_done: rays_done.fetch_add(tile_rays, Ordering::Relaxed) + tile_rays,
+                    rays_total: num_rays,
+                    elapsed: t0.elapsed(),
+                });
+            }
+        });
+    let count = RayCount {
+        traced: rays_traced.into_inner(),
+        bounce_limited: rays_limited.into_inner(),
+        medium_mismatches: rays_mismatched.into_inner(),
+    };
+    (result, count)
+}
+
+/// Rectangular block of pixels, given by its ranges of rows and columns of the image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub x: Range<usize>,
+    pub y: Range<usize>,
+}
+
+impl Tile {
+    pub fn num_pixels(&self) -> usize {
+        self.x.len() * self.y.len()
+    }
+
+    /// Row and column of the k-th pixel of the tile, row by row.
+    pub fn pixel(&self, k: usize) -> (usize, usize) {
+        (
+            self.x.start + k / self.y.len(),
+            self.y.start + k % self.y.len(),
+        )
+    }
+
+    /// Tile extended by a border of the given width, within an image of `num_x` rows and `num_y`
+    /// columns.
+    pub fn grow(&self, border: usize, num_x: usize, num_y: usize) -> Tile {
+        Tile {
+            x: self.x.start.saturating_sub(border)..(self.x.end + border).min(num_x),
+            y: self.y.start.saturating_sub(border)..(self.y.end + border).min(num_y),
+        }
+    }
+
+    /// Index of the k-th pixel of the tile, row by row, in the row by row pixels of a larger
+    /// tile that contains it.
+    pub fn index_in(&self, outer: &Tile, k: usize) -> usize {
+        (self.x.start - outer.x.start + k / self.y.len()) * outer.y.len() + self.y.start
+            - outer.y.start
+            + k % self.y.len()
+    }
+}
+
+/// Trace some of the tiles of a pass in parallel. The mean pixel colors of each tile, row by row,
+/// are handed to `on_tile` with the tile's index in the given slice and the numbers of rays traced
+/// as soon as it is done, from the worker threads. See `trace_pass`.
+pub fn trace_tiles<F>(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    pass: usize,
+    tiles: &[Tile],
+    on_tile: F,
+) where
+    F: Fn(usize, Vec<[f64; 3]>, RayCount) + Sync,
+{
+    tiles.par_iter().enumerate().for_each(|(i, tile)| {
+        let (pixels, count) = trace_tile(camera, scene, tile.x.clone(), tile.y.clone(), pass);
+        on_tile(i, pixels, count)
+    });
+}
+
+/// Trace the rays of a single tile and return its filtered pixel colors, row by row, along with
+/// the numbers of rays traced.
+fn trace_tile(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    x: Range<usize>,
+    y: Range<usize>,
+    pass: usize,
+) -> (Vec<[f64; 3]>, RayCount) {
+    if let Some(adaptive) = &camera.adaptive {
+        return trace_tile_adaptive(camera, scene, x, y, pass, adaptive);
+    }
+    if camera.filter != Filter::Box {
+        return trace_tile_filtered(camera, scene, Tile { x, y }, pass);
+    }
+    let (colors, counts) = trace_samples(camera, scene, camera.tile_rays(x, y, pass));
+    (
+        colors
+            .chunks(camera.antialiasing.pow(2))
+            .map(mean_array3)
+            .collect(),
+        counts.iter().sum(),
+    )
+}
+
+/// Version of `trace_tile` for filters wider than a pixel. The tile is traced with a border of
+/// the pixels within reach of the filter, whose samples also count towards the pixels of the tile.
+fn trace_tile_filtered(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    tile: Tile,
+    pass: usize,
+) -> (Vec<[f64; 3]>, RayCount) {
+    let n = camera.antialiasing;
+    let reach = camera.filter.reach();
+    let border = tile.grow(reach, camera.num_x, camera.num_y);
+
+    // sample positions in pixels, from the top left corner of the image
+    let mut positions: Vec<[f64; 2]> = Vec::with_capacity(border.num_pixels() * n * n);
+    let mut rays: Vec<Ray> = Vec::with_capacity(border.num_pixels() * n * n);
+    for i in border.x.clone() {
+        for j in border.y.clone() {
+            for (k, sample) in camera.pixel_samples(i, j, n, pass, 0).iter().enumerate() {
+                let [u, v] = sample.position(n);
+                positions.push([i as f64 + u, j as f64 + v]);
+                rays.push(camera.sample_ray(i, j, n, pass, k, sample));
+            }
+        }
+    }
+    let (colors, counts) = trace_samples(camera, scene, rays);
+
+    // samples of the border are only counted by the tile they belong to
+    let count = (0..tile.num_pixels())
+        .map(|k| tile.index_in(&border, k) * n * n)
+        .flat_map(|first| &counts[first..first + n * n])
+        .sum();
+    let result = (0..tile.num_pixels())
+        .map(|k| {
+            let (i, j) = tile.pixel(k);
+            let (mut sum, mut total) = ([0.0; 3], 0.0);
+            for ni in i.saturating_sub(reach).max(border.x.start)..(i + reach + 1).min(border.x.end)
+            {
+                for nj in
+                    j.saturating_sub(reach).max(border.y.start)..(j + reach + 1).min(border.y.end)
+                {
+                    let first =
+                        ((ni - border.x.start) * border.y.len() + nj - border.y.start) * n * n;
+                    for s in first..first + n * n {
+                        let weight = camera.filter.weight([
+                            positions[s][0] - (i as f64 + 0.5),
+                            positions[s][1] - (j as f64 + 0.5),
+                        ]);
+                        for c in 0..3 {
+                            sum[c] += weight * colors[s][c] as f64;
+                        }
+                        total += weight;
+                    }
+                }
+            }
+            sum.map(|c| c / total)
+        })
+        .collect();
+    (result, count)
+}
+
+/// Launch the given rays and return their colors, along with the count of each of them.
+fn trace_samples(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    mut rays: Vec<Ray>,
+) -> (Vec<[u8; 3]>, Vec<RayCount>) {
+    // primary rays are coherent, so launch them in packets
+    let bounces: Vec<BounceResult> = rays
+        .chunks_mut(PACKET_SIZE)
+        .flat_map(|packet| launch_packet(packet, scene, &camera.bounce_limit))
+        .collect();
+    bounces
+        .into_iter()
+        .zip(&rays)
+        .map(|(bounce, ray)| {
+            let (color, bounce_limited) = match bounce {
+                BounceResult::Count(r, g, b) => ([r, g, b], false),
+                BounceResult::Kill => ([0, 0, 0], false),
+                BounceResult::Limit(r, g, b) => ([r, g, b], true),
+                _ => panic!("Something has gone wrong."),
+            };
+            let count = RayCount {
+                traced: 1,
+                bounce_limited: bounce_limited as usize,
+                medium_mismatches: (ray.media.mismatches > 0) as usize,
+            };
+            (color, count)
+        })
+        .unzip()
 }
 
-/// Average a slice of [u8; 3] arrays.
-fn average_array3(arr3: &[[u8; 3]]) -> [u8; 3] {
-    let mut result: [usize; 3] = [0; 3];
-    let l = arr3.len();
-    for n in 0..=2 {
-        for elem in arr3 {
-            result[n] += elem[n] as usize;
+/// Adaptive version of `trace_tile`. The tile is traced with a one pixel border, so that pixels
+/// on its edges can be compared with their neighbours in the next tile.
+fn trace_tile_adaptive(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    x: Range<usize>,
+    y: Range<usize>,
+    pass: usize,
+    adaptive: &Adaptive,
+) -> (Vec<[f64; 3]>, RayCount) {
+    let tile = Tile { x, y };
+    let border = tile.grow(1, camera.num_x, camera.num_y);
+    let (colors, counts) = trace_samples(
+        camera,
+        scene,
+        camera.tile_rays(border.x.clone(), border.y.clone(), pass),
+    );
+    let mut count = RayCount::default();
+    let samples: Vec<&[[u8; 3]]> = colors.chunks(camera.antialiasing.pow(2)).collect();
+    let counts: Vec<&[RayCount]> = counts.chunks(camera.antialiasing.pow(2)).collect();
+    let means: Vec<[f64; 3]> = samples.iter().map(|s| mean_array3(s)).collect();
+
+    let mut result: Vec<[f64; 3]> = Vec::with_capacity(tile.num_pixels());
+    for k in 0..tile.num_pixels() {
+        let index = tile.index_in(&border, k);
+        let (i, j) = tile.pixel(k);
+
+        // largest difference with the pixel's neighbours, inside the traced border
+        let neighbours = [
+            (i > border.x.start).then(|| index - border.y.len()),
+            (i + 1 < border.x.end).then(|| index + border.y.len()),
+            (j > border.y.start).then(|| index - 1),
+            (j + 1 < border.y.end).then(|| index + 1),
+        ];
+        let contrast = neighbours
+            .iter()
+            .flatten()
+            .map(|&n| max_difference(&means[index], &means[n]))
+            .fold(color_range(samples[index]), f64::max);
+
+        // refine by doubling the subdivision while the samples disagree, with new samples after
+        // those already taken, all of them kept in the mean
+        let mut sum = means[index].map(|c| c * samples[index].len() as f64);
+        let mut num_samples = samples[index].len();
+        // rays of the border are only counted by the tile they belong to
+        count.add(counts[index].iter().sum());
+        let mut refine = contrast > adaptive.threshold;
+        let mut antialiasing = camera.antialiasing;
+        while refine && antialiasing < adaptive.max_antialiasing {
+            antialiasing = (2 * antialiasing).min(adaptive.max_antialiasing);
+            let rays = camera.pixel_rays(i, j, antialiasing, pass, num_samples);
+            let (colors, counts) = trace_samples(camera, scene, rays);
+            count.add(counts.iter().sum());
+            for color in &colors {
+                for c in 0..3 {
+                    sum[c] += color[c] as f64;
+                }
+            }
+            num_samples += colors.len();
+            refine = color_range(&colors) > adaptive.threshold;
         }
-        result[n] /= l;
+        result.push(sum.map(|c| c / num_samples as f64));
     }
-    [result[0] as u8, result[1] as u8, result[2] as u8]
+    (result, count)
+}
+
+/// Largest difference between two colors, over all channels.
+fn max_difference(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (0..3).map(|n| (a[n] - b[n]).abs()).fold(0.0, f64::max)
+}
+
+/// Largest spread of a set of colors, over all channels.
+fn color_range(colors: &[[u8; 3]]) -> f64 {
+    (0..3)
+        .map(|n| {
+            let values = colors.iter().map(|c| c[n]);
+            (values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)) as f64
+        })
+        .fold(0.0, f64::max)
 }
 
-/// Combine rays by their antialiasing chunking. E.g. for AA=3, chunks of 9 will be averaged
-/// together.
-pub fn combine_rays(results: Vec<[u8; 3]>, antialiasing: usize) -> Vec<[u8; 3]> {
-    results
-        .chunks(antialiasing.pow(2))
-        .map(|chunk| average_array3(chunk))
-        .collect()
+/// Mean of a slice of [u8; 3] arrays, without rounding.
+fn mean_array3(arr3: &[[u8; 3]]) -> [f64; 3] {
+    let mut result: [f64; 3] = [0.0; 3];
+    for elem in arr3 {
+        for n in 0..=2 {
+            result[n] += elem[n] as f64;
+        }
+    }
+    result.map(|sum| sum / arr3.len() as f64)
 }
 
 /// Save a vector of [u8; 3] data to an image.
@@ -110,52 +458,279 @@ pub fn save_jpg(filepath: &str, data: Vec<[u8; 3]>, num_x: usize, num_y: usize)
     let _ = img.save(filepath);
 }
 
-#[derive(Debug)]
+/// Save the pixels of the camera window, row by row, to an image. See `frame_image`.
+pub fn save_image(filepath: &str, camera: &Camera, data: Vec<[u8; 3]>) {
+    let (data, num_x, num_y) = frame_image(camera, data);
+    save_jpg(filepath, data, num_x, num_y);
+}
+
+/// Image to save for the pixels of the camera window, row by row, along with its number of rows
+/// and columns. A cropped window is saved either on its own or in place in the full frame, with
+/// the rest of the frame black.
+pub fn frame_image(camera: &Camera, data: Vec<[u8; 3]>) -> (Vec<[u8; 3]>, usize, usize) {
+    let window = &camera.window;
+    if camera.full_frame {
+        let frame = Tile {
+            x: 0..camera.num_x,
+            y: 0..camera.num_y,
+        };
+        let mut full: Vec<[u8; 3]> = vec![[0; 3]; frame.num_pixels()];
+        for (k, pixel) in data.into_iter().enumerate() {
+            full[window.index_in(&frame, k)] = pixel;
+        }
+        (full, camera.num_x, camera.num_y)
+    } else {
+        (data, window.x.len(), window.y.len())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     origin: Point3<f64>,
-    screen_corner: Point3<f64>,
+    gaze: Unit<Vector3<f64>>,
+    up: Unit<Vector3<f64>>,
+    right: Unit<Vector3<f64>>,
+    projection: Projection,
+    mapping: FisheyeMapping,
     screen_local_to_world: Isometry3<f64>,
     size_x: f64,
     size_y: f64,
     pub num_x: usize,
     pub num_y: usize,
     pub antialiasing: usize,
+    /// Pixels to render, the whole frame unless cropped.
+    pub window: Tile,
+    /// Whether to save a cropped window in place in the full frame.
+    pub full_frame: bool,
+    /// Refine pixels with more samples where needed, instead of a fixed number per pixel.
+    pub adaptive: Option<Adaptive>,
+    sampling: Sampling,
+    filter: Filter,
+    /// Seed of the scene, that all random numbers are derived from.
+    pub seed: u64,
+    /// Time of the image in seconds, that the shutter interval counts from.
+    pub time: f64,
+    /// Maximum number of bounces of the rays, and their color when they reach it.
+    pub bounce_limit: BounceLimit,
+    /// Thin lens, a pinhole camera if none.
+    lens: Option<Lens>,
+    distortion: Option<Distortion>,
+    vignetting: Option<Vignetting>,
+    /// Interval of time the rays travel in, all at time 0 if none.
+    shutter: Option<Shutter>,
+    /// Stereo rig, rendering a view per eye.
+    pub stereo: Option<Stereo>,
+    /// Eye of the stereo rig this camera renders the view of, if any.
+    eye: Option<Eye>,
     vop: Arc<VOP>,
 }
 
 impl Camera {
-    fn pixel_corners(&self) -> Vec<Point3<f64>> {
-        split_rectangle(
-            Point3::origin(),
-            self.size_x,
-            self.size_y,
-            self.num_x,
-            self.num_y,
-            true,
-        )
+    /// Tiles covering the camera window, band of rows by band of rows.
+    pub fn tiles(&self) -> Vec<Tile> {
+        let window = &self.window;
+        window
+            .x
+            .clone()
+            .step_by(TILE_SIZE)
+            .flat_map(|x_start| {
+                window
+                    .y
+                    .clone()
+                    .step_by(TILE_SIZE)
+                    .map(move |y_start| Tile {
+                        x: x_start..(x_start + TILE_SIZE).min(window.x.end),
+                        y: y_start..(y_start + TILE_SIZE).min(window.y.end),
+                    })
+            })
+            .collect()
+    }
+
+    /// Samples of a single pixel, split into `antialiasing` x `antialiasing` subpixels, for the
+    /// given pass, after the given number of samples already taken in it.
+    fn pixel_samples(
+        &self,
+        i: usize,
+        j: usize,
+        antialiasing: usize,
+        pass: usize,
+        taken: usize,
+    ) -> Vec<Sample> {
+        self.sampling
+            .pixel_samples(self.seed, i * self.num_y + j, antialiasing, pass, taken)
     }
 
-    fn subpixel_centers(&self) -> Vec<Point3<f64>> {
+    /// Point in screen coordinates of a sample of a pixel split into `antialiasing` x
+    /// `antialiasing` subpixels.
+    fn sample_point(
+        &self,
+        i: usize,
+        j: usize,
+        antialiasing: usize,
+        sample: &Sample,
+    ) -> Point3<f64> {
         let pixel_size_x: f64 = self.size_x / self.num_x as f64;
         let pixel_size_y: f64 = self.size_y / self.num_y as f64;
-        self.pixel_corners()
-            .into_iter()
-            .map(|pxc| {
-                split_rectangle(
-                    pxc,
-                    pixel_size_x,
-                    pixel_size_y,
-                    self.antialiasing,
-                    self.antialiasing,
-                    false,
-                )
-            })
-            .flatten()
+        let corner = Point3::new(i as f64 * pixel_size_x, j as f64 * pixel_size_y, 0.0);
+
+        // spacing between adjacent subpixels
+        let dx: f64 = pixel_size_x / antialiasing as f64;
+        let dy: f64 = pixel_size_y / antialiasing as f64;
+        let mut v = Vector3::new(sample.cell[0] as f64 * dx, sample.cell[1] as f64 * dy, 0.0);
+        v.x += sample.offset[0] * dx;
+        v.y += sample.offset[1] * dy;
+        corner + v
+    }
+
+    /// Rays through the samples of the pixels in the given rows and columns, pixel by pixel, for
+    /// the given pass.
+    pub fn tile_rays(&self, x: Range<usize>, y: Range<usize>, pass: usize) -> Vec<Ray> {
+        x.flat_map(|i| y.clone().map(move |j| (i, j)))
+            .flat_map(|(i, j)| self.pixel_rays(i, j, self.antialiasing, pass, 0))
+            .collect()
+    }
+
+    /// Rays through the samples of a single pixel, split into `antialiasing` x `antialiasing`
+    /// subpixels, for the given pass, after the given number of samples already taken in it.
+    fn pixel_rays(
+        &self,
+        i: usize,
+        j: usize,
+        antialiasing: usize,
+        pass: usize,
+        taken: usize,
+    ) -> Vec<Ray> {
+        self.pixel_samples(i, j, antialiasing, pass, taken)
+            .iter()
+            .enumerate()
+            .map(|(k, sample)| self.sample_ray(i, j, antialiasing, pass, taken + k, sample))
             .collect()
     }
 
+    /// Ray for the k-th sample of a pixel split into `antialiasing` x `antialiasing` subpixels,
+    /// in the given pass.
+    fn sample_ray(
+        &self,
+        i: usize,
+        j: usize,
+        antialiasing: usize,
+        pass: usize,
+        k: usize,
+        sample: &Sample,
+    ) -> Ray {
+        let mut ray = self.ray_through(&self.sample_point(i, j, antialiasing, sample));
+        if let Some(lens) = &self.lens {
+            let mut rng = Rng::for_sample(self.seed, Stream::Lens, i * self.num_y + j, pass, k);
+            let (origin, direction) = lens.refocus(ray.origin, ray.direction, &mut rng);
+            ray.origin = origin;
+            ray.direction = direction;
+        }
+        if let Some(shutter) = &self.shutter {
+            let mut rng = Rng::for_sample(self.seed, Stream::Time, i * self.num_y + j, pass, k);
+            ray.time = self.time + shutter.time(rng.next_f64());
+        }
+        ray.path.rng = Rng::for_sample(self.seed, Stream::Scatter, i * self.num_y + j, pass, k);
+        ray
+    }
+
+    /// Ray through a point given in screen coordinates, from the camera origin or, for an
+    /// orthographic projection, from the matching point of the camera plane. For angular
+    /// projections the screen coordinates are angles, and points without a ray get a fully
+    /// absorbed one so that they stay black.
+    fn ray_through(&self, screen_point: &Point3<f64>) -> Ray {
+        let mut abs = [0.0; 3];
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => {
+                let screen_point = match &self.distortion {
+                    Some(distortion) => {
+                        self.undistort(distortion, screen_point).unwrap_or_else(|| {
+                            abs = [f64::INFINITY; 3];
+                            *screen_point
+                        })
+                    }
+                    None => *screen_point,
+                };
+                (
+                    self.origin,
+                    self.screen_local_to_world * screen_point - self.origin,
+                )
+            }
+            Projection::Orthographic => (
+                self.screen_local_to_world * screen_point - self.gaze.into_inner(),
+                self.gaze.into_inner(),
+            ),
+            Projection::Equirectangular | Projection::Fisheye => {
+                match angular_direction(
+                    self.projection,
+                    self.mapping,
+                    [self.size_x, self.size_y],
+                    [screen_point.x, screen_point.y],
+                ) {
+                    Some([g, u, r]) => (
+                        self.origin,
+                        g * self.gaze.into_inner()
+                            + u * self.up.into_inner()
+                            + r * self.right.into_inner(),
+                    ),
+                    None => {
+                        abs = [f64::INFINITY; 3];
+                        (self.origin, self.gaze.into_inner())
+                    }
+                }
+            }
+        };
+        let (origin, direction) = match (&self.stereo, self.eye) {
+            (Some(stereo), Some(eye)) => stereo.eye_ray(
+                eye,
+                self.projection,
+                [&self.gaze, &self.up, &self.right],
+                origin,
+                direction,
+            ),
+            _ => (origin, direction),
+        };
+        if let Some(vignetting) = &self.vignetting {
+            // start with the absorption that leaves the vignetting factor of the light
+            let cos_angle = direction.normalize().dot(&self.gaze);
+            abs = abs.map(|a| a - vignetting.factor(cos_angle).ln());
+        }
+        Ray {
+            origin,
+            direction,
+            vop: self.vop.clone(),
+            abs,
+            time: self.time,
+            media: Default::default(),
+            path: Default::default(),
+        }
+    }
+
+    /// Point of the ideal screen that the lens distortion moves to the given screen point, if
+    /// the lens reaches it.
+    fn undistort(
+        &self,
+        distortion: &Distortion,
+        screen_point: &Point3<f64>,
+    ) -> Option<Point3<f64>> {
+        // image coordinates from the screen center, x to the right and y down
+        let (center_x, center_y) = (self.size_x / 2.0, self.size_y / 2.0);
+        let [x, y] =
+            distortion.undistort([screen_point.y - center_y, screen_point.x - center_x])?;
+        Some(Point3::new(y + center_x, x + center_y, screen_point.z))
+    }
+
+    /// Camera rendering the view of one eye of the stereo rig.
+    pub fn for_eye(&self, eye: Eye) -> Camera {
+        Camera {
+            eye: Some(eye),
+            ..self.clone()
+        }
+    }
+
+    /// Rays through the samples of the first pass over the camera window, pixel by pixel. With
+    /// the default grid pattern, these go through the subpixel centers.
     pub fn create_rays(&self) -> Vec<Ray> {
-        let num_pixels: usize = self.num_x * self.num_y;
+        let num_pixels: usize = self.window.num_pixels();
         print!(
             "Generating {} x {}^2 = {} rays... ",
             num_pixels,
@@ -163,16 +738,7 @@ impl Camera {
             num_pixels * self.antialiasing.pow(2)
         );
         let t0 = Instant::now();
-        let rays: Vec<Ray> = self
-            .subpixel_centers()
-            .into_iter()
-            .map(|sbpxc| Ray {
-                origin: self.origin,
-                direction: self.screen_local_to_world * sbpxc - self.origin,
-                vop: self.vop.clone(),
-                abs: [0.0; 3],
-            })
-            .collect();
+        let rays: Vec<Ray> = self.tile_rays(self.window.x.clone(), self.window.y.clone(), 0);
         println!(
             "done! Total time: {}s.",
             t0.elapsed().as_millis() as f64 / 1000.0
@@ -184,24 +750,101 @@ impl Camera {
 #[derive(Debug, Deserialize)]
 pub struct CameraBuilder {
     origin: [f64; 3],
-    gaze: [f64; 3],
+    gaze: Option<[f64; 3]>,
+    /// Point to look at, instead of a gaze direction.
+    look_at: Option<[f64; 3]>,
     up: [f64; 3],
-    fov: [f64; 2],
-    density: f64,
+    #[serde(default)]
+    projection: Projection,
+    #[serde(default)]
+    mapping: FisheyeMapping,
+    /// Field of view in degrees, for all but an orthographic projection.
+    fov: Option<[f64; 2]>,
+    /// Sensor [width, height] in mm, giving the field of view along with the focal length.
+    sensor_size: Option<[f64; 2]>,
+    /// Focal length in mm.
+    focal_length: Option<f64>,
+    /// Screen size in world units, for an orthographic projection.
+    screen_size: Option<[f64; 2]>,
+    /// Pixels per degree of field of view, or per world unit of screen size.
+    density: Option<f64>,
+    /// Image [width, height] in pixels, instead of a density.
+    resolution: Option<[usize; 2]>,
     #[serde(default = "default_antialiasing")]
     antialiasing: usize,
     vop: String,
+    crop: Option<CropBuilder>,
+    adaptive: Option<Adaptive>,
+    #[serde(default)]
+    sampling: Sampling,
+    #[serde(default)]
+    filter: Filter,
+    lens: Option<LensBuilder>,
+    distortion: Option<Distortion>,
+    vignetting: Option<Vignetting>,
+    shutter: Option<Shutter>,
+    stereo: Option<Stereo>,
 }
 
 fn default_antialiasing() -> usize {
     1
 }
 
+/// Adaptive antialiasing. Every pixel is first traced with `antialiasing` x `antialiasing`
+/// samples; pixels whose samples, or whose mean and that of a neighbouring pixel, differ by more
+/// than `threshold` on any channel (0-255 color units) are traced again with twice the
+/// subdivision, until their samples agree or `max_antialiasing` is reached.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Adaptive {
+    pub max_antialiasing: usize,
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+}
+
+fn default_threshold() -> f64 {
+    8.0
+}
+
+/// Crop window, given in image coordinates as [left, top, right, bottom], either in pixels
+/// (right and bottom excluded) or as fractions of the image size. Only the pixels inside are
+/// rendered; they are saved as a cropped image, or in place in an otherwise black full frame.
+#[derive(Debug, Deserialize)]
+pub struct CropBuilder {
+    pixels: Option<[usize; 4]>,
+    fraction: Option<[f64; 4]>,
+    #[serde(default)]
+    full_frame: bool,
+}
+
+impl CropBuilder {
+    /// Window of pixels to render, for an image of `num_x` rows and `num_y` columns.
+    fn build(&self, num_x: usize, num_y: usize) -> Tile {
+        let [left, top, right, bottom] = match (self.pixels, self.fraction) {
+            (Some(pixels), None) => pixels,
+            (None, Some([left, top, right, bottom])) => [
+                (left * num_y as f64).floor() as usize,
+                (top * num_x as f64).floor() as usize,
+                (right * num_y as f64).ceil() as usize,
+                (bottom * num_x as f64).ceil() as usize,
+            ],
+            _ => panic!("Crop window must be given either in pixels or as a fraction."),
+        };
+        let window = Tile {
+            x: top.min(num_x)..bottom.min(num_x),
+            y: left.min(num_y)..right.min(num_y),
+        };
+        if window.x.is_empty() || window.y.is_empty() {
+            panic!("Crop window must contain at least one pixel of the image.")
+        }
+        window
+    }
+}
+
 impl CameraBuilder {
     pub fn build(self, vop_map: &HashMap<String, Arc<VOP>>) -> Camera {
         // allow approximate "up" direction
         let origin = Point3::from_slice(&self.origin);
-        let gaze: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::from_row_slice(&self.gaze));
+        let gaze: Unit<Vector3<f64>> = Unit::new_normalize(self.gaze_direction());
         let mut up: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::from_row_slice(&self.up));
         up = Unit::new_normalize(gaze.cross(&up.cross(&gaze))); // QUESTION: use unchecked here?
 
@@ -211,9 +854,54 @@ impl CameraBuilder {
             + up.into_inner() * size_x / 2.0
             + up.cross(&gaze) * size_y / 2.0;
 
+        if self
+            .stereo
+            .as_ref()
+            .is_some_and(|stereo| stereo.mode == StereoMode::ToeIn && stereo.convergence.is_none())
+        {
+            panic!("Toe-in stereo needs a convergence distance.")
+        }
+        if self.distortion.is_some() && self.projection != Projection::Perspective {
+            panic!("Lens distortion only applies to a perspective projection.")
+        }
+        if self.adaptive.is_some() && self.filter != Filter::Box {
+            panic!("Adaptive antialiasing only supports the box filter.")
+        }
+        if self
+            .shutter
+            .as_ref()
+            .is_some_and(|shutter| shutter.close < shutter.open)
+        {
+            panic!("Shutter must close after it opens.")
+        }
+
+        let extent = match self.projection {
+            Projection::Orthographic => self.orthographic_size(),
+            _ => self.fov(),
+        };
+        let (num_x, num_y) = match (self.resolution, self.density) {
+            (Some([width, height]), None) => (height, width),
+            (None, Some(density)) => (
+                (extent[0] * density) as usize,
+                (extent[1] * density) as usize,
+            ),
+            _ => panic!("Camera needs either a density or a resolution."),
+        };
+        let window = match &self.crop {
+            Some(crop) => crop.build(num_x, num_y),
+            None => Tile {
+                x: 0..num_x,
+                y: 0..num_y,
+            },
+        };
+
         Camera {
             origin,
-            screen_corner,
+            gaze,
+            up,
+            right: Unit::new_normalize(gaze.cross(&up)),
+            projection: self.projection,
+            mapping: self.mapping,
             screen_local_to_world: Isometry3::look_at_lh(
                 &screen_corner,
                 &(screen_corner - gaze.into_inner()),
@@ -222,9 +910,26 @@ impl CameraBuilder {
             .inverse(),
             size_x,
             size_y,
-            num_x: (self.fov[0] * self.density) as usize,
-            num_y: (self.fov[1] * self.density) as usize,
+            num_x,
+            num_y,
             antialiasing: self.antialiasing,
+            window,
+            full_frame: self.crop.as_ref().is_some_and(|crop| crop.full_frame),
+            adaptive: self.adaptive,
+            sampling: self.sampling,
+            filter: self.filter,
+            seed: 0,
+            time: 0.0,
+            bounce_limit: BounceLimit::default(),
+            lens: self
+                .lens
+                .as_ref()
+                .map(|lens| lens.build(up, Unit::new_normalize(gaze.cross(&up)))),
+            distortion: self.distortion,
+            vignetting: self.vignetting,
+            shutter: self.shutter,
+            stereo: self.stereo,
+            eye: None,
             vop: vop_map
                 .get(&self.vop)
                 .expect("No VOP above mapping found.")
@@ -234,27 +939,116 @@ impl CameraBuilder {
 
     /// Get the real screen size.
     fn screen_size(&self) -> (f64, f64) {
-        (
-            2.0 * (self.fov[0] / 2.0).to_radians().tan(),
-            2.0 * (self.fov[1] / 2.0).to_radians().tan(),
-        )
+        match self.projection {
+            Projection::Perspective => {
+                let fov = self.fov();
+                (
+                    2.0 * (fov[0] / 2.0).to_radians().tan(),
+                    2.0 * (fov[1] / 2.0).to_radians().tan(),
+                )
+            }
+            Projection::Orthographic => {
+                let size = self.orthographic_size();
+                (size[0], size[1])
+            }
+            // angular projections measure the screen in radians
+            Projection::Equirectangular | Projection::Fisheye => {
+                let fov = self.fov();
+                (fov[0].to_radians(), fov[1].to_radians())
+            }
+        }
+    }
+
+    fn gaze_direction(&self) -> Vector3<f64> {
+        match (self.gaze, self.look_at) {
+            (Some(gaze), None) => Vector3::from_row_slice(&gaze),
+            (None, Some(target)) => Point3::from_slice(&target) - Point3::from_slice(&self.origin),
+            _ => panic!("Camera needs either a gaze or a look_at point."),
+        }
+    }
+
+    /// Field of view in degrees, given directly or by the sensor size and focal length.
+    fn fov(&self) -> [f64; 2] {
+        let fov = match (self.fov, self.sensor_size, self.focal_length) {
+            (Some(fov), None, None) => fov,
+            (None, Some([width, height]), Some(focal_length)) => {
+                // angle from the gaze of the edges of the sensor
+                let half_angle = |size: f64| match self.projection {
+                    Projection::Perspective => (size / (2.0 * focal_length)).atan(),
+                    // both sides at the image scale of the longer one, see `angular_direction`
+                    Projection::Fisheye => {
+                        let longer = width.max(height);
+                        self.mapping
+                            .angle(longer / (2.0 * focal_length))
+                            .expect("Sensor too large for the fisheye mapping.")
+                            * size
+                            / longer
+                    }
+                    _ => panic!(
+                        "Sensor size and focal length only apply to perspective and fisheye \
+                         projections."
+                    ),
+                };
+                [height, width].map(|size| 2.0 * half_angle(size).to_degrees())
+            }
+            _ => panic!("Camera needs either a fov, or a sensor size and focal length."),
+        };
+        let max_fov = match self.projection {
+            Projection::Perspective => [180.0 - f64::EPSILON; 2],
+            Projection::Orthographic => [f64::INFINITY; 2],
+            Projection::Equirectangular => [180.0, 360.0],
+            Projection::Fisheye => [self.mapping.max_fov(); 2],
+        };
+        if fov[0] > max_fov[0] || fov[1] > max_fov[1] {
+            panic!(
+                "Field of view {:?} too wide for a {:?} projection.",
+                fov, self.projection
+            )
+        }
+        fov
+    }
+
+    fn orthographic_size(&self) -> [f64; 2] {
+        self.screen_size
+            .expect("An orthographic camera needs a screen_size.")
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::TOLERANCE;
+    use serde_yaml::{Mapping, Value};
 
     fn camera(vop: Arc<VOP>, fov: [f64; 2]) -> Camera {
+        cropped_camera(vop, fov, None)
+    }
+
+    fn cropped_camera(vop: Arc<VOP>, fov: [f64; 2], crop: Option<CropBuilder>) -> Camera {
         let cb = CameraBuilder {
             origin: [0.0, 0.0, 0.0],
-            gaze: [0.0, 1.0, 0.0],
+            gaze: Some([0.0, 1.0, 0.0]),
+            look_at: None,
             up: [0.0, 0.0, 1.0],
-            fov,
-            density: 1.0,
+            projection: Projection::Perspective,
+            mapping: FisheyeMapping::Equidistant,
+            fov: Some(fov),
+            sensor_size: None,
+            focal_length: None,
+            screen_size: None,
+            density: Some(1.0),
+            resolution: None,
             antialiasing: 1,
             vop: "air".to_owned(),
+            crop,
+            adaptive: None,
+            sampling: Sampling::default(),
+            filter: Filter::Box,
+            lens: None,
+            distortion: None,
+            vignetting: None,
+            shutter: None,
+            stereo: None,
         };
         let mut vop_map: HashMap<String, Arc<VOP>> = HashMap::new();
         vop_map.insert("air".to_owned(), vop);
@@ -262,12 +1056,37 @@ mod tests {
         cb.build(&vop_map)
     }
 
+    /// Volumes of the test scenes, only air.
+    fn vops() -> HashMap<String, Arc<VOP>> {
+        let mut vop_map: HashMap<String, Arc<VOP>> = HashMap::new();
+        vop_map.insert("air".to_owned(), Arc::new(VOP::new(1.0, [0.0; 3])));
+        vop_map
+    }
+
+    /// Camera at the origin looking along the y axis through air, with the given settings added to
+    /// or replacing these, in front of a light sphere.
+    pub(crate) fn scene_camera(yaml: &str) -> (Camera, Vec<Arc<dyn Surface + Send + Sync>>) {
+        use crate::surface::{SphereBuilder, SurfaceBuilder};
+
+        let mut settings: Mapping =
+            serde_yaml::from_str("{origin: [0, 0, 0], gaze: [0, 1, 0], up: [0, 0, 1], vop: air}")
+                .unwrap();
+        settings.extend(serde_yaml::from_str::<Mapping>(yaml).unwrap());
+        let vop_map = vops();
+        let camera = serde_yaml::from_value::<CameraBuilder>(Value::Mapping(settings))
+            .unwrap()
+            .build(&vop_map);
+        let sphere: SphereBuilder = serde_yaml::from_str(
+            "{center: [0, 10, 0], radius: 3, sop: {light: [255, 128, 0]}, vop_above: air, \
+             vop_below: air}",
+        )
+        .unwrap();
+        (camera, vec![sphere.build(&vop_map)])
+    }
+
     #[test]
     fn screen_size() {
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let c = camera(air, [20.0, 30.0]);
         let theo = (0.35265, 0.53590);
         assert!((c.size_x - theo.0).abs() <= 1e-5);
@@ -276,15 +1095,280 @@ mod tests {
 
     #[test]
     fn ray_centering() {
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0, 0.0, 0.0],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0, 0.0, 0.0]));
         let c = camera(air, [1.0, 1.0]);
-        let centers = c.subpixel_centers();
-        assert_eq!(centers.len(), 1);
+        let rays = c.create_rays();
+        assert_eq!(rays.len(), 1);
         assert!(
-            (c.screen_local_to_world * centers[0] - Point3::new(0.0, 1.0, 0.0)).norm() < TOLERANCE
+            (rays[0].origin + rays[0].direction - Point3::new(0.0, 1.0, 0.0)).norm() < TOLERANCE
         );
     }
+
+    /// A tile must hold the same rays as the matching pixels of the whole image.
+    #[test]
+    fn tile_rays() {
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
+        let c = camera(air, [4.0, 5.0]);
+        let all = c.create_rays();
+        let tile = c.tile_rays(1..3, 2..5, 0);
+        assert_eq!(tile.len(), 6);
+        for (k, ray) in tile.iter().enumerate() {
+            let (i, j) = (1 + k / 3, 2 + k % 3);
+            assert_eq!(ray.direction, all[i * c.num_y + j].direction);
+        }
+    }
+
+    /// Progress must be reported per tile and account for every ray by the end.
+    #[test]
+    fn progress_reports_all_rays() {
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
+        let c = camera(air, [40.0, 20.0]);
+        let calls = AtomicUsize::new(0);
+        let last = AtomicUsize::new(0);
+        let result = trace_rays(&c, &[], |progress| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            last.fetch_max(progress.rays_done, Ordering::Relaxed);
+            assert_eq!(progress.rays_total, 800);
+        });
+        assert_eq!(result.len(), 800);
+        assert_eq!(calls.into_inner(), 3 * 2);
+        assert_eq!(last.into_inner(), 800);
+    }
+
+    #[test]
+    fn crop_window() {
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
+        let full = camera(air.clone(), [4.0, 10.0]);
+        let crop = |pixels, fraction| CropBuilder {
+            pixels,
+            fraction,
+            full_frame: false,
+        };
+
+        let c = cropped_camera(
+            air.clone(),
+            [4.0, 10.0],
+            Some(crop(Some([2, 1, 7, 3]), None)),
+        );
+        assert_eq!(c.window, Tile { x: 1..3, y: 2..7 });
+        let all = full.create_rays();
+        for (k, ray) in c.create_rays().iter().enumerate() {
+            assert_eq!(
+                ray.direction,
+                all[c.window.index_in(&full.window, k)].direction
+            );
+        }
+        assert_eq!(trace_rays(&c, &[], |_| {}).len(), 10);
+
+        let c = cropped_camera(
+            air,
+            [4.0, 10.0],
+            Some(crop(None, Some([0.25, 0.25, 0.75, 0.75]))),
+        );
+        assert_eq!(c.window, Tile { x: 1..3, y: 2..8 });
+    }
+
+    /// Only pixels on the edge of the sphere must be refined, the flat background and the inside
+    /// of the sphere keep their base samples.
+    #[test]
+    fn adaptive_refines_edges() {
+        let (mut c, scene) = scene_camera("{fov: [40, 40], density: 1}");
+        let uniform = trace_pass(&c, &scene, 0, |_| {});
+        c.adaptive = Some(Adaptive {
+            max_antialiasing: 4,
+            threshold: 8.0,
+        });
+        let (pixels, count) = trace_pass(&c, &scene, 0, |_| {});
+        assert!(count.traced > 1600 && count.traced < 1600 * 16);
+
+        // far from the edge, the pixels are not refined
+        assert_eq!(pixels[0], uniform.0[0]);
+        assert_eq!(pixels[20 * 40 + 20], [255.0, 128.0, 0.0]);
+        // on the edge, the pixel is partly covered
+        let edge = (0..40)
+            .map(|j| pixels[20 * 40 + j][0])
+            .find(|&r| r > 0.0 && r < 255.0);
+        assert!(edge.is_some());
+
+        // refined pixels keep their base samples in the mean
+        c.adaptive = Some(Adaptive {
+            max_antialiasing: 2,
+            threshold: 8.0,
+        });
+        let (pixels, _) = trace_pass(&c, &scene, 0, |_| {});
+        let k = (0..40)
+            .map(|j| 20 * 40 + j)
+            .find(|&k| pixels[k][0] > 0.0 && pixels[k][0] < 255.0)
+            .unwrap();
+        let (base, _) = trace_samples(&c, &scene, c.pixel_rays(20, k % 40, 1, 0, 0));
+        let (refined, _) = trace_samples(&c, &scene, c.pixel_rays(20, k % 40, 2, 0, 1));
+        let all: Vec<[u8; 3]> = base.into_iter().chain(refined).collect();
+        assert_eq!(pixels[k], mean_array3(&all));
+
+        // nothing to refine in an empty scene
+        let (_, count) = trace_pass(&c, &[], 0, |_| {});
+        assert_eq!(count.traced, 1600);
+    }
+
+    /// Rays trapped between facing mirrors must be stopped and counted, once per sample whatever
+    /// the tiling.
+    #[test]
+    fn bounce_limit_counts() {
+        use crate::{
+            ray::LimitResult,
+            surface::{PlaneBuilder, SurfaceBuilder},
+        };
+
+        let vop_map = vops();
+        let mirror = |y: f64| {
+            let plane: PlaneBuilder = serde_yaml::from_str(&format!(
+                "{{origin: [0, {}, 0], normal: [0, 1, 0], sop: reflect, vop_above: air, \
+                 vop_below: air}}",
+                y
+            ))
+            .unwrap();
+            plane.build(&vop_map)
+        };
+        let scene = vec![mirror(-1.0), mirror(1.0)];
+
+        let (mut c, _) = scene_camera("{fov: [40, 40], density: 1}");
+        c.bounce_limit = BounceLimit {
+            max: 10,
+            result: LimitResult::Debug(255, 0, 255),
+        };
+        for filter in [Filter::Box, Filter::Mitchell] {
+            c.filter = filter;
+            let (pixels, count) = trace_pass(&c, &scene, 0, |_| {});
+            assert_eq!(count.traced, 1600);
+            assert_eq!(count.bounce_limited, 1600);
+            assert!(pixels
+                .iter()
+                .all(|p| (p[0] - 255.0).abs() < TOLERANCE && p[1].abs() < TOLERANCE));
+        }
+    }
+
+    /// Filtered tiles must match the image filtered in one go, with no seams at the tile edges.
+    #[test]
+    fn filtered_tiles() {
+        let (mut c, scene) = scene_camera("{fov: [40, 40], density: 1}");
+        c.antialiasing = 2;
+        c.sampling.pattern = crate::sampling::Pattern::Jittered;
+        c.filter = Filter::Mitchell;
+        let (tiled, count) = trace_pass(&c, &scene, 3, |_| {});
+        assert_eq!(count.traced, 1600 * 4);
+        let (whole, _) = trace_tile_filtered(&c, &scene, c.window.clone(), 3);
+        assert_eq!(tiled, whole);
+
+        // the filter keeps flat areas flat
+        assert_eq!(tiled[0], [0.0; 3]);
+        assert!(tiled[20 * 40 + 20]
+            .iter()
+            .zip(&[255.0, 128.0, 0.0])
+            .all(|(a, b)| (a - b).abs() < 1e-9));
+    }
+
+    /// Random sampling must give the same image whatever the number of threads.
+    #[test]
+    fn same_image_across_threads() {
+        let (mut c, scene) = scene_camera("{fov: [40, 40], density: 1}");
+        c.antialiasing = 2;
+        c.sampling.pattern = crate::sampling::Pattern::Random;
+        c.filter = Filter::Tent;
+        let render = |c: &Camera, threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| trace_pass(c, &scene, 1, |_| {}).0)
+        };
+        c.seed = 42;
+        let image = render(&c, 1);
+        assert_eq!(image, render(&c, 4));
+        c.seed = 43;
+        assert_ne!(image, render(&c, 4));
+    }
+
+    /// Orthographic rays must all follow the gaze, from a grid on the camera plane.
+    #[test]
+    fn orthographic_projection() {
+        let (c, _) = scene_camera(
+            "{origin: [1, 2, 3], projection: orthographic, screen_size: [2, 4], density: 2}",
+        );
+        assert_eq!((c.num_x, c.num_y), (4, 8));
+
+        let rays = c.create_rays();
+        for ray in &rays {
+            assert!((ray.direction - Vector3::new(0.0, 1.0, 0.0)).norm() < TOLERANCE);
+            assert!((ray.origin.y - 2.0).abs() < TOLERANCE);
+        }
+        // the corners of the screen are 2 x 4 units apart, less half a pixel on each side
+        let (first, last) = (&rays[0].origin, &rays[rays.len() - 1].origin);
+        assert!(((first.z - last.z).abs() - 1.5).abs() < TOLERANCE);
+        assert!(((first.x - last.x).abs() - 3.5).abs() < TOLERANCE);
+        assert!(((first.x + last.x) / 2.0 - 1.0).abs() < TOLERANCE);
+    }
+
+    /// A camera given by its sensor, focal length, resolution and target must match the same
+    /// camera given by its field of view, density and gaze.
+    #[test]
+    fn physical_camera() {
+        let build = |yaml: &str| scene_camera(yaml).0;
+
+        let physical = build(
+            "{origin: [1, 2, 3], gaze: null, look_at: [1, 12, 3], sensor_size: [36, 24], \
+             focal_length: 50, resolution: [60, 40]}",
+        );
+        assert_eq!((physical.num_x, physical.num_y), (40, 60));
+        assert!((physical.size_x - 24.0 / 50.0).abs() < TOLERANCE);
+        assert!((physical.size_y - 36.0 / 50.0).abs() < TOLERANCE);
+
+        let fov = [2.0 * 0.24_f64.atan(), 2.0 * 0.36_f64.atan()].map(f64::to_degrees);
+        let classic = build(&format!(
+            "{{origin: [1, 2, 3], fov: [{}, {}], resolution: [60, 40]}}",
+            fov[0], fov[1]
+        ));
+        for (a, b) in physical.create_rays().iter().zip(classic.create_rays()) {
+            assert!((a.origin - b.origin).norm() < TOLERANCE);
+            assert!((a.direction.normalize() - b.direction.normalize()).norm() < TOLERANCE);
+        }
+    }
+
+    /// Distorted rays must go through the ideal screen points that the lens moves to their
+    /// pixels, and vignetting must darken the edges only.
+    #[test]
+    fn distortion_and_vignetting() {
+        let build = |extra: &str| {
+            scene_camera(&format!("{{fov: [60, 90], resolution: [9, 6], {}}}", extra)).0
+        };
+        let ideal = build("");
+        let distorted = build("distortion: {k1: -0.1, p1: 0.01}, vignetting: {}");
+        let distortion = distorted.distortion.clone().unwrap();
+
+        for (a, b) in ideal.create_rays().iter().zip(distorted.create_rays()) {
+            // image coordinates of both rays, from the gaze: x to the right (+x), y down (-z)
+            let image = |d: &Vector3<f64>| [d.x / d.y, -d.z / d.y];
+            let moved = distortion.distort(image(&b.direction));
+            assert!((moved[0] - image(&a.direction)[0]).abs() < 1e-9);
+            assert!((moved[1] - image(&a.direction)[1]).abs() < 1e-9);
+
+            let cos_angle = b.direction.normalize().y;
+            assert!(((-b.abs[0]).exp() - cos_angle.powi(4)).abs() < 1e-9);
+        }
+        assert_eq!(ideal.create_rays()[0].abs, [0.0; 3]);
+    }
+
+    /// Samples must travel at times spread over the shutter interval, the same for every run.
+    #[test]
+    fn shutter_times() {
+        let (camera, _) = scene_camera(
+            "{fov: [40, 40], resolution: [8, 8], antialiasing: 4, shutter: {open: 2, close: 3}}",
+        );
+
+        let times: Vec<f64> = camera.create_rays().iter().map(|ray| ray.time).collect();
+        assert!(times.iter().all(|time| (2.0..3.0).contains(time)));
+        let mean = times.iter().sum::<f64>() / times.len() as f64;
+        assert!((mean - 2.5).abs() < 0.05);
+        let again: Vec<f64> = camera.create_rays().iter().map(|ray| ray.time).collect();
+        assert_eq!(times, again);
+    }
 }
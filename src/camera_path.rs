@@ -0,0 +1,229 @@
+use {
+    nalgebra::{Point3, Rotation3, Unit, Vector3},
+    serde::Deserialize,
+    serde_yaml::{from_value, to_value, Mapping, Value},
+    std::f64::consts::PI,
+};
+
+/// Motion of the camera over an animation, replacing the placement of the camera in each frame.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CameraPath {
+    /// Circle about an axis through the target, starting from the camera origin and looking at
+    /// the target throughout.
+    Orbit {
+        target: [f64; 3],
+        /// Axis of the circle, the camera up direction if not given.
+        axis: Option<[f64; 3]>,
+        #[serde(default = "default_turns")]
+        turns: f64,
+    },
+    /// Rotation of the whole camera about an axis through a center, as if the scene turned on a
+    /// turntable in front of it.
+    Turntable {
+        center: [f64; 3],
+        axis: [f64; 3],
+        #[serde(default = "default_turns")]
+        turns: f64,
+    },
+    /// Smooth curve through the waypoints, looking at the target or, without one, along the
+    /// curve.
+    Spline {
+        waypoints: Vec<[f64; 3]>,
+        look_at: Option<[f64; 3]>,
+    },
+}
+
+fn default_turns() -> f64 {
+    1.0
+}
+
+impl CameraPath {
+    pub fn validate(&self) {
+        if let CameraPath::Spline { waypoints, .. } = self {
+            if waypoints.len() < 2 {
+                panic!("A spline camera path needs at least 2 waypoints.")
+            }
+        }
+    }
+
+    /// Fraction of the path covered at a given frame. Closed paths end a frame short of a full
+    /// loop, so that the animation repeats seamlessly; splines end on their last waypoint.
+    pub fn fraction(&self, frame: usize, frames: usize) -> f64 {
+        match self {
+            CameraPath::Spline { .. } => frame as f64 / (frames.max(2) - 1) as f64,
+            _ => frame as f64 / frames as f64,
+        }
+    }
+
+    /// Place the camera of a scene where the path is at the given fraction.
+    pub fn apply(&self, camera: &mut Mapping, fraction: f64) {
+        let origin = Point3::from(vector(camera, "origin").expect("No camera origin given."));
+        let up = vector(camera, "up").expect("No camera up direction given.");
+        match self {
+            CameraPath::Orbit {
+                target,
+                axis,
+                turns,
+            } => {
+                let axis = Unit::new_normalize(axis.map_or(up, Vector3::from));
+                let rotation = Rotation3::from_axis_angle(&axis, 2.0 * PI * turns * fraction);
+                let target = Point3::from(*target);
+                set(
+                    camera,
+                    "origin",
+                    (target + rotation * (origin - target)).coords,
+                );
+                set(camera, "up", rotation * up);
+                look_at(camera, target);
+            }
+            CameraPath::Turntable {
+                center,
+                axis,
+                turns,
+            } => {
+                let axis = Unit::new_normalize(Vector3::from(*axis));
+                let rotation = Rotation3::from_axis_angle(&axis, 2.0 * PI * turns * fraction);
+                let center = Point3::from(*center);
+                let turn = |point: Point3<f64>| center + rotation * (point - center);
+                set(camera, "origin", turn(origin).coords);
+                set(camera, "up", rotation * up);
+                match vector(camera, "look_at") {
+                    Some(target) => look_at(camera, turn(Point3::from(target))),
+                    None => {
+                        let gaze = vector(camera, "gaze").expect("No camera gaze given.");
+                        set(camera, "gaze", rotation * gaze);
+                    }
+                }
+            }
+            CameraPath::Spline {
+                waypoints,
+                look_at: target,
+            } => {
+                let (position, tangent) = catmull_rom(waypoints, fraction);
+                set(camera, "origin", position.coords);
+                match target {
+                    Some(target) => look_at(camera, Point3::from(*target)),
+                    None => {
+                        camera.remove(&key("look_at"));
+                        set(camera, "gaze", tangent);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_owned())
+}
+
+fn vector(camera: &Mapping, name: &str) -> Option<Vector3<f64>> {
+    camera.get(&key(name)).map(|value| {
+        let vector: [f64; 3] = from_value(value.clone())
+            .unwrap_or_else(|_| panic!("Camera {} must be a list of 3 numbers.", name));
+        Vector3::from(vector)
+    })
+}
+
+fn set(camera: &mut Mapping, name: &str, vector: Vector3<f64>) {
+    let components: [f64; 3] = vector.into();
+    camera.insert(key(name), to_value(components).unwrap());
+}
+
+fn look_at(camera: &mut Mapping, target: Point3<f64>) {
+    camera.remove(&key("gaze"));
+    set(camera, "look_at", target.coords);
+}
+
+/// Position and direction along a Catmull-Rom spline through the waypoints, each segment between
+/// two waypoints taking an equal share of the fraction.
+fn catmull_rom(waypoints: &[[f64; 3]], fraction: f64) -> (Point3<f64>, Vector3<f64>) {
+    let point = |k: isize| {
+        let k = k.clamp(0, waypoints.len() as isize - 1) as usize;
+        Vector3::from(waypoints[k])
+    };
+    let segments = waypoints.len() - 1;
+    let u = fraction.clamp(0.0, 1.0) * segments as f64;
+    let k = (u as usize).min(segments - 1);
+    let t = u - k as f64;
+    let k = k as isize;
+    let (p0, p1, p2, p3) = (point(k - 1), point(k), point(k + 1), point(k + 2));
+    let a = p2 - p0;
+    let b = 2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3;
+    let c = -p0 + 3.0 * p1 - 3.0 * p2 + p3;
+    let position = p1 + 0.5 * (a * t + b * t * t + c * t * t * t);
+    let tangent = 0.5 * (a + 2.0 * b * t + 3.0 * c * t * t);
+    (Point3::from(position), tangent)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::TOLERANCE, serde_yaml::from_str};
+
+    fn camera() -> Mapping {
+        from_str("{origin: [10, 0, 0], gaze: [-1, 0, 0], up: [0, 0, 1]}").unwrap()
+    }
+
+    fn placed(path: &str, fraction: f64) -> Mapping {
+        let path: CameraPath = from_str(path).unwrap();
+        path.validate();
+        let mut camera = camera();
+        path.apply(&mut camera, fraction);
+        camera
+    }
+
+    fn close(camera: &Mapping, name: &str, expected: [f64; 3]) -> bool {
+        (vector(camera, name).unwrap() - Vector3::from(expected)).norm() < TOLERANCE
+    }
+
+    /// Orbits and turntables must circle the axis, orbits looking at the target throughout.
+    #[test]
+    fn circular_paths() {
+        let orbit = placed("{type: orbit, target: [0, 0, 5]}", 0.25);
+        assert!(close(&orbit, "origin", [0.0, 10.0, 0.0]));
+        assert!(close(&orbit, "look_at", [0.0, 0.0, 5.0]));
+        assert!(orbit.get(&key("gaze")).is_none());
+
+        let turntable = placed(
+            "{type: turntable, center: [5, 0, 0], axis: [0, 0, 1], turns: 2}",
+            0.25,
+        );
+        assert!(close(&turntable, "origin", [0.0, 0.0, 0.0]));
+        assert!(close(&turntable, "gaze", [1.0, 0.0, 0.0]));
+        assert!(close(&turntable, "up", [0.0, 0.0, 1.0]));
+
+        let path: CameraPath = from_str("{type: orbit, target: [0, 0, 0]}").unwrap();
+        assert_eq!(path.fraction(3, 4), 0.75);
+    }
+
+    /// Splines must go through every waypoint, with or without a target to look at.
+    #[test]
+    fn spline_paths() {
+        let waypoints = "[[0, 0, 0], [4, 0, 0], [4, 4, 0]]";
+        let along = format!("{{type: spline, waypoints: {}}}", waypoints);
+        for (fraction, expected) in [
+            (0.0, [0.0; 3]),
+            (0.5, [4.0, 0.0, 0.0]),
+            (1.0, [4.0, 4.0, 0.0]),
+        ] {
+            assert!(close(&placed(&along, fraction), "origin", expected));
+        }
+        let start = placed(&along, 0.0);
+        let gaze = vector(&start, "gaze").unwrap();
+        assert!(gaze.normalize().x > 1.0 - TOLERANCE);
+
+        let looking = placed(
+            &format!(
+                "{{type: spline, waypoints: {}, look_at: [1, 1, 1]}}",
+                waypoints
+            ),
+            0.3,
+        );
+        assert!(close(&looking, "look_at", [1.0, 1.0, 1.0]));
+        assert!(looking.get(&key("gaze")).is_none());
+
+        let path: CameraPath = from_str(&along).unwrap();
+        assert_eq!(path.fraction(4, 5), 1.0);
+    }
+}
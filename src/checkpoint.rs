@@ -0,0 +1,128 @@
+use {
+    crate::progressive::Film,
+    serde::{Deserialize, Serialize},
+    std::fs::{self, File},
+    std::io::{BufReader, BufWriter},
+};
+
+/// Where and how often to save the state of a render.
+#[derive(Debug, Deserialize)]
+pub struct CheckpointSettings {
+    pub path: String,
+    /// Minimum time between two checkpoints in the middle of a pass, in seconds. A checkpoint is
+    /// also saved after every pass.
+    #[serde(default = "default_interval")]
+    pub interval: f64,
+}
+
+fn default_interval() -> f64 {
+    60.0
+}
+
+/// Checkpoint settings of a render, along with the hash of the scene file being rendered.
+pub struct Checkpointer {
+    pub settings: CheckpointSettings,
+    pub scene_hash: u64,
+}
+
+impl Checkpointer {
+    /// Load the checkpoint to resume from, refusing to do so if it was saved for another scene.
+    pub fn resume(&self) -> Checkpoint {
+        let checkpoint = Checkpoint::load(&self.settings.path);
+        if checkpoint.scene_hash != self.scene_hash {
+            panic!(
+                "Scene file has changed since checkpoint {} was saved, refusing to resume.",
+                self.settings.path
+            )
+        }
+        checkpoint
+    }
+}
+
+/// State of an interrupted render: the accumulated film, which tiles of the pass in progress it
+/// already holds, the numbers of rays traced, stopped by the bounce limit and meeting surfaces from
+/// an unexpected medium, and the time spent so far.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub scene_hash: u64,
+    pub film: Film,
+    pub tiles_done: Vec<bool>,
+    pub rays_traced: usize,
+    pub bounce_limited: usize,
+    pub medium_mismatches: usize,
+    /// Render time, in seconds.
+    pub elapsed: f64,
+}
+
+impl Checkpoint {
+    /// Save the checkpoint, going through a temporary file so that an interruption while saving
+    /// leaves the previous checkpoint intact. Failing to save does not stop the render.
+    pub fn save(&self, path: &str) {
+        let temporary = format!("{}.tmp", path);
+        let result = File::create(&temporary)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                bincode::serialize_into(BufWriter::new(file), self).map_err(|e| e.to_string())
+            })
+            .and_then(|_| fs::rename(&temporary, path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            println!("Could not save checkpoint {}: {}", path, e);
+        }
+    }
+
+    pub fn load(path: &str) -> Self {
+        let file = File::open(path).expect("Could not open checkpoint.");
+        bincode::deserialize_from(BufReader::new(file)).expect("Could not parse checkpoint.")
+    }
+}
+
+/// FNV-1a hash of the contents of a scene file.
+pub fn scene_hash(contents: &str) -> u64 {
+    contents.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn fnv_hash() {
+        assert_eq!(scene_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(scene_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(scene_hash("density: 80.0"), scene_hash("density: 81.0"));
+    }
+
+    #[test]
+    fn save_and_resume() {
+        let path = env::temp_dir().join(format!("raytracer-checkpoint-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_owned();
+        let mut film = Film::new(2);
+        film.add_pass(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        Checkpoint {
+            scene_hash: scene_hash("scene"),
+            film,
+            tiles_done: vec![true, false],
+            rays_traced: 2,
+            bounce_limited: 0,
+            medium_mismatches: 0,
+            elapsed: 1.5,
+        }
+        .save(&path);
+
+        let checkpointer = |contents| Checkpointer {
+            settings: CheckpointSettings {
+                path: path.clone(),
+                interval: 60.0,
+            },
+            scene_hash: scene_hash(contents),
+        };
+        let checkpoint = checkpointer("scene").resume();
+        assert_eq!(checkpoint.film.average(), vec![[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(checkpoint.tiles_done, vec![true, false]);
+        assert!(std::panic::catch_unwind(|| checkpointer("other scene").resume()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use {
+    crate::random::Rng,
+    nalgebra::{Point3, Unit, Vector3},
+    serde::Deserialize,
+    std::f64::consts::PI,
+};
+
+/// Thin lens in front of the camera origin. Rays start from a random point of the aperture and
+/// converge on the plane at the focus distance, so that objects away from it are blurred.
+#[derive(Debug, Clone)]
+pub struct Lens {
+    /// Radius of the aperture, or of the circle through the corners of a polygonal one.
+    pub radius: f64,
+    /// Distance from the lens to the plane in focus, along the gaze.
+    pub focus_distance: f64,
+    /// Number of blades of a polygonal aperture, round if none.
+    pub blades: Option<usize>,
+    /// Rotation of a polygonal aperture, in radians.
+    pub rotation: f64,
+    up: Unit<Vector3<f64>>,
+    right: Unit<Vector3<f64>>,
+}
+
+impl Lens {
+    /// Ray through the lens from a random point of the aperture, converging with the pinhole ray
+    /// from the camera origin on the focus plane. The pinhole direction must have a component of 1
+    /// along the gaze.
+    pub fn refocus(
+        &self,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+        rng: &mut Rng,
+    ) -> (Point3<f64>, Vector3<f64>) {
+        let [u, v] = self.sample_aperture(rng);
+        let lens_point =
+            origin + self.radius * (u * self.up.into_inner() + v * self.right.into_inner());
+        let focus_point = origin + direction * self.focus_distance;
+        (lens_point, focus_point - lens_point)
+    }
+
+    /// Random point of the aperture, uniformly distributed over its area, in units of the radius.
+    fn sample_aperture(&self, rng: &mut Rng) -> [f64; 2] {
+        let (a, b) = (rng.next_f64(), rng.next_f64());
+        match self.blades {
+            None => concentric_disk(2.0 * a - 1.0, 2.0 * b - 1.0),
+            Some(blades) => {
+                // pick a triangle of the polygon, then a point inside it
+                let scaled = a * blades as f64;
+                let blade = (scaled as usize).min(blades - 1);
+                let (s, t) = ((scaled - blade as f64).sqrt(), b);
+                let corner = |k: usize| {
+                    let angle = self.rotation + 2.0 * PI * k as f64 / blades as f64;
+                    [angle.cos(), angle.sin()]
+                };
+                let (p, q) = (corner(blade), corner(blade + 1));
+                [
+                    s * ((1.0 - t) * p[0] + t * q[0]),
+                    s * ((1.0 - t) * p[1] + t * q[1]),
+                ]
+            }
+        }
+    }
+}
+
+/// Shirley's concentric mapping of the [-1, 1] square onto the unit disk, preserving areas.
+fn concentric_disk(a: f64, b: f64) -> [f64; 2] {
+    if a == 0.0 && b == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    [r * theta.cos(), r * theta.sin()]
+}
+
+/// Thin lens configuration. The aperture is given either as a radius or as an f-number along
+/// with the focal length, both in scene units.
+#[derive(Debug, Deserialize)]
+pub struct LensBuilder {
+    aperture: Option<f64>,
+    f_number: Option<f64>,
+    focal_length: Option<f64>,
+    focus_distance: f64,
+    blades: Option<usize>,
+    /// Rotation of a polygonal aperture, in degrees.
+    #[serde(default)]
+    rotation: f64,
+}
+
+impl LensBuilder {
+    /// Lens with the given camera orientation, the aperture lying in the plane of `up` and
+    /// `right`.
+    pub fn build(&self, up: Unit<Vector3<f64>>, right: Unit<Vector3<f64>>) -> Lens {
+        let radius = match (self.aperture, self.f_number) {
+            (Some(aperture), None) => aperture,
+            (None, Some(f_number)) => {
+                self.focal_length
+                    .expect("An f-number needs the focal length of the lens.")
+                    / (2.0 * f_number)
+            }
+            _ => panic!("Lens aperture must be given either as a radius or as an f-number."),
+        };
+        if self.blades.is_some_and(|blades| blades < 3) {
+            panic!("A polygonal aperture needs at least 3 blades.")
+        }
+        if self.focus_distance <= 0.0 {
+            panic!("Focus distance must be positive.")
+        }
+        Lens {
+            radius,
+            focus_distance: self.focus_distance,
+            blades: self.blades,
+            rotation: self.rotation.to_radians(),
+            up,
+            right,
+        }
+    }
+}
+
+/// Brown-Conrady lens distortion, with the OpenCV conventions: the coefficients map ideal image
+/// coordinates to distorted ones, both in units of the focal length from the image center, x to
+/// the right and y down.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Distortion {
+    #[serde(default)]
+    pub k1: f64,
+    #[serde(default)]
+    pub k2: f64,
+    #[serde(default)]
+    pub k3: f64,
+    #[serde(default)]
+    pub p1: f64,
+    #[serde(default)]
+    pub p2: f64,
+}
+
+impl Distortion {
+    /// Where the lens moves an ideal image point.
+    pub fn distort(&self, point: [f64; 2]) -> [f64; 2] {
+        let [x, y] = point;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        [
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        ]
+    }
+
+    /// Ideal image point the lens moves to a given distorted one, found by fixed-point iteration.
+    /// Strong distortions cannot reach every point of the image, in which case there is none.
+    pub fn undistort(&self, point: [f64; 2]) -> Option<[f64; 2]> {
+        let [xd, yd] = point;
+        let [mut x, mut y] = point;
+        for _ in 0..50 {
+            let r2 = x * x + y * y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+            let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+            let next = [(xd - dx) / radial, (yd - dy) / radial];
+            let change = (next[0] - x).abs() + (next[1] - y).abs();
+            x = next[0];
+            y = next[1];
+            if change < 1e-14 {
+                return Some([x, y]);
+            }
+        }
+        // slow convergence still counts if the point is moved where it should
+        let distorted = self.distort([x, y]);
+        Some([x, y]).filter(|_| (distorted[0] - xd).abs() + (distorted[1] - yd).abs() < 1e-9)
+    }
+}
+
+/// Darkening of the image away from its center, by a factor of cos^`power` of the angle from
+/// the gaze, scaled by `strength`: 1 for the full falloff, 0 for none.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Vignetting {
+    #[serde(default = "default_vignetting_power")]
+    pub power: f64,
+    #[serde(default = "default_vignetting_strength")]
+    pub strength: f64,
+}
+
+fn default_vignetting_power() -> f64 {
+    4.0
+}
+
+fn default_vignetting_strength() -> f64 {
+    1.0
+}
+
+impl Vignetting {
+    /// Fraction of the light kept for a ray at the given cosine of its angle from the gaze.
+    pub fn factor(&self, cos_angle: f64) -> f64 {
+        1.0 - self.strength * (1.0 - cos_angle.max(0.0).powf(self.power))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::random::Stream, crate::TOLERANCE};
+
+    fn lens(blades: Option<usize>) -> Lens {
+        let builder: LensBuilder = serde_yaml::from_str(&format!(
+            "{{f_number: 2, focal_length: 0.4, focus_distance: 5, blades: {}}}",
+            blades.map_or("null".to_owned(), |b| b.to_string())
+        ))
+        .unwrap();
+        builder.build(Vector3::z_axis(), Vector3::x_axis())
+    }
+
+    /// Rays must leave from inside the aperture and meet the pinhole ray on the focus plane.
+    #[test]
+    fn rays_converge_on_focus_plane() {
+        let origin = Point3::new(1.0, 2.0, 3.0);
+        let direction = Vector3::new(0.2, 1.0, -0.1);
+        for blades in [None, Some(3), Some(6)] {
+            let lens = lens(blades);
+            assert!((lens.radius - 0.1).abs() < TOLERANCE);
+            for k in 0..100 {
+                let mut rng = Rng::for_sample(0, Stream::Lens, 0, 0, k);
+                let (start, towards) = lens.refocus(origin, direction, &mut rng);
+                let offset = start - origin;
+                assert!(offset.y.abs() < TOLERANCE);
+                assert!(offset.norm() <= lens.radius + TOLERANCE);
+                let end = start + towards;
+                assert!((end - (origin + 5.0 * direction)).norm() < TOLERANCE);
+            }
+        }
+    }
+
+    /// A triangular aperture must only give points inside the triangle.
+    #[test]
+    fn polygonal_aperture() {
+        let lens = lens(Some(3));
+        for k in 0..1000 {
+            let mut rng = Rng::for_sample(1, Stream::Lens, 0, 0, k);
+            let [u, v] = lens.sample_aperture(&mut rng);
+            // the edge between the corners at 120 and 240 degrees
+            assert!(u >= -0.5 - TOLERANCE);
+            assert!(u * u + v * v <= 1.0 + TOLERANCE);
+        }
+        let disk = concentric_disk(1.0, 1.0);
+        assert!((disk[0].hypot(disk[1]) - 1.0).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn distortion_inverse() {
+        let distortion = Distortion {
+            k1: -0.2,
+            k2: 0.05,
+            k3: 0.01,
+            p1: 0.001,
+            p2: -0.002,
+        };
+        for &point in &[[0.0, 0.0], [0.3, -0.2], [-0.5, 0.4], [0.6, 0.6]] {
+            let distorted = distortion.distort(point);
+            let back = distortion.undistort(distorted).unwrap();
+            assert!((back[0] - point[0]).abs() < 1e-9 && (back[1] - point[1]).abs() < 1e-9);
+        }
+        // barrel distortion pulls the edges in, out of reach of the far corners
+        assert!(distortion.distort([0.5, 0.0])[0] < 0.5);
+        assert!(distortion.undistort([2.0, 2.0]).is_none());
+    }
+
+    #[test]
+    fn vignetting_falloff() {
+        let vignetting: Vignetting = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(vignetting.factor(1.0), 1.0);
+        assert!((vignetting.factor(0.5) - 0.0625).abs() < TOLERANCE);
+        let half: Vignetting = serde_yaml::from_str("{strength: 0.5}").unwrap();
+        assert!((half.factor(0.5) - 0.53125).abs() < TOLERANCE);
+    }
+}
@@ -1,14 +1,32 @@
+pub mod animation;
 pub mod camera;
+pub mod camera_path;
+pub mod checkpoint;
 pub mod colormap;
+pub mod lens;
+pub mod medium;
+pub mod motion;
+pub mod packet;
+pub mod progressive;
+pub mod projection;
+pub mod random;
 pub mod ray;
+pub mod sampling;
+pub mod scattering;
+pub mod scene;
+pub mod stereo;
 pub mod surface;
 pub mod vop;
 
 pub use {
     camera::Camera,
     ray::{BounceResult, Ray},
-    surface::{Surface, SOP},
+    surface::{Hit, Surface, SOP},
     vop::VOP,
 };
 
 pub const TOLERANCE: f64 = 1e-5;
+
+/// Distance by which rays leaving a surface start off it, relative to the size of the coordinates
+/// of the point they leave from, so that rounding errors cannot make them hit it again there.
+pub const SURFACE_OFFSET: f64 = 1e-8;
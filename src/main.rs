@@ -1,142 +1,128 @@
 use {
+    indicatif::{ProgressBar, ProgressStyle},
     rayon::ThreadPoolBuilder,
     raytracer::{
-        camera::{combine_rays, save_jpg, trace_rays, Camera, CameraBuilder},
-        surface::{
-            CheckerboardBuilder, CylinderBuilder, MandelbrotPlaneBuilder, ParaboloidBuilder,
-            PlaneBuilder, RectangleBuilder, SphereBuilder, SurfaceBuilder,
-            TexturedRectangleBuilder,
+        animation::frame_path,
+        camera::{frame_image, save_image, save_jpg, trace_rays, Camera},
+        checkpoint::{scene_hash, Checkpoint, Checkpointer},
+        progressive::{render_progressive, Progressive},
+        scene::{
+            extract_animation, extract_camera, extract_checkpoint, extract_filepath,
+            extract_progressive, extract_surfaces, extract_threads, extract_vops,
         },
-        Ray, Surface, VOP,
+        stereo::{Eye, Stereo},
+        Surface,
     },
-    serde_yaml::{from_str, from_value, Mapping, Value},
-    std::{collections::HashMap, env, fs, sync::Arc},
+    serde_yaml::{from_str, Mapping},
+    std::{env, fs, sync::Arc},
 };
 
-/// Load the given configuration file and return its contents as a parsed yaml hash.
-fn load_from_yaml() -> Mapping {
+/// Load the given configuration file and return its raw contents.
+fn load_file() -> String {
     let args: Vec<String> = env::args().collect();
 
-    let contents =
-        fs::read_to_string(args[1].clone()).expect("Something went wrong reading the file");
-
-    from_str(&contents).expect("Error in parsing the file")
+    fs::read_to_string(args[1].clone()).expect("Something went wrong reading the file")
 }
 
-/// Extract the path to the image to be saved.
-fn extract_filepath(lhm: &Mapping) -> String {
-    lhm.get(&Value::String("filepath".to_owned()))
-        .expect("No filepath given.")
-        .as_str()
-        .expect("Filepath must be a string.")
-        .to_owned()
+/// Whether the `--resume` flag was given after the configuration file.
+fn resume_requested() -> bool {
+    env::args().skip(2).any(|arg| arg == "--resume")
 }
 
-/// Extract VOPs.
-fn extract_vops(lhm: &Mapping) -> HashMap<String, Arc<VOP>> {
-    let volumes = lhm
-        .get(&Value::String("volumes".to_owned()))
-        .expect("No volumes given.")
-        .as_mapping()
-        .expect("Volumes must be given as dictionary.");
-
-    volumes
-        .into_iter()
-        .map(|(k, v)| {
-            (
-                k.as_str()
-                    .expect("Volume names must be strings.")
-                    .to_owned(),
-                Arc::new(from_value(v.clone()).expect("Could not parse volume")),
-            )
-        })
-        .collect()
+/// Terminal progress bar over all the rays of the given number of passes of the camera.
+fn progress_bar(camera: &Camera, passes: usize) -> ProgressBar {
+    let pbar =
+        ProgressBar::new((passes * camera.window.num_pixels() * camera.antialiasing.pow(2)) as u64);
+    pbar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40}] {percent}% {per_sec} ETA {eta}")
+            .progress_chars("=> "),
+    );
+    pbar
 }
 
-/// Get the camera configuration.
-fn extract_camera(lhm: &Mapping, vop_map: &HashMap<String, Arc<VOP>>) -> Camera {
-    let camera_builder: CameraBuilder = from_value(
-        lhm.get(&Value::String("camera".to_owned()))
-            .expect("No camera given.")
-            .to_owned(),
-    )
-    .expect("Could not parse camera.");
-    camera_builder.build(vop_map)
-}
+fn raytrace(camera: &Camera, scene: &[Arc<dyn Surface + Send + Sync>], filepath: &str) {
+    let pbar = progress_bar(camera, 1);
+    let result = trace_rays(camera, scene, |progress| {
+        pbar.set_position(progress.rays_done as u64)
+    });
+    pbar.finish_and_clear();
 
-fn extract_threads(lhm: &Mapping) -> Option<usize> {
-    match lhm.get(&Value::String("threads".to_owned())) {
-        Some(v) => Some(v.as_u64().expect("Number of threads must be an integer.") as usize),
-        None => None,
-    }
+    save_image(filepath, camera, result);
 }
 
-fn extract_surfaces(
-    lhm: &Mapping,
-    vop_map: &HashMap<String, Arc<VOP>>,
-) -> Vec<Arc<dyn Surface + Send + Sync>> {
-    let surfaces = lhm
-        .get(&Value::String("surfaces".to_owned()))
-        .expect("No surfaces give")
-        .as_sequence()
-        .expect("Surfaces must be a list.")
-        .to_owned();
-
-    let mut surface_list = Vec::new();
-
-    for s in surfaces.iter() {
-        let surface = match s
-            .as_mapping()
-            .expect("Each surfacemust be given as a mapping.")
-            .get(&Value::String("type".to_owned()))
-            .expect("Surface must have a type.")
-            .as_str()
-            .expect("Surface type must be a string.")
-        {
-            "checkerboard" => from_value::<CheckerboardBuilder>(s.to_owned())
-                .expect("Error parsing checkerboard.")
-                .build(vop_map),
-            "rectangle" => from_value::<RectangleBuilder>(s.to_owned())
-                .expect("Error parsing rectangle.")
-                .build(vop_map),
-            "texturedrectangle" => from_value::<TexturedRectangleBuilder>(s.to_owned())
-                .expect("Error parsing textured rectangle.")
-                .build(vop_map),
-            "plane" => from_value::<PlaneBuilder>(s.to_owned())
-                .expect("Error parsing plane.")
-                .build(vop_map),
-            "mandelbrotplane" => from_value::<MandelbrotPlaneBuilder>(s.to_owned())
-                .expect("Error parsing Mandelbrot plane.")
-                .build(vop_map),
-            "sphere" => from_value::<SphereBuilder>(s.to_owned())
-                .expect("Error parsing sphere.")
-                .build(vop_map),
-            "paraboloid" => from_value::<ParaboloidBuilder>(s.to_owned())
-                .expect("Error parsing paraboloid.")
-                .build(vop_map),
-            "cylinder" => from_value::<CylinderBuilder>(s.to_owned())
-                .expect("Error parsing cylinder.")
-                .build(vop_map),
-            _ => panic!("Unknown surface type"),
-        };
-        surface_list.push(surface);
+/// Render the views of both eyes of the stereo rig and save them in the rig's layout.
+fn raytrace_stereo(
+    camera: &Camera,
+    stereo: &Stereo,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    filepath: &str,
+) {
+    let [left, right] = [Eye::Left, Eye::Right].map(|eye| {
+        println!("Rendering {} eye.", eye.name());
+        let camera = camera.for_eye(eye);
+        let pbar = progress_bar(&camera, 1);
+        let result = trace_rays(&camera, scene, |progress| {
+            pbar.set_position(progress.rays_done as u64)
+        });
+        pbar.finish_and_clear();
+        frame_image(&camera, result)
+    });
+    for (path, (data, num_x, num_y)) in stereo.images(filepath, left, right) {
+        save_jpg(&path, data, num_x, num_y);
     }
-
-    surface_list
 }
 
-fn raytrace(camera: &Camera, scene: &[Arc<dyn Surface + Send + Sync>], filepath: &str) {
-    let rays: Vec<Ray> = camera.create_rays();
-    save_jpg(
-        filepath,
-        combine_rays(trace_rays(rays, scene), camera.antialiasing),
-        camera.num_x,
-        camera.num_y,
+/// Load the scene of a document and render it to the given path.
+fn render(
+    document: &Mapping,
+    filepath: &str,
+    checkpointer: Option<&Checkpointer>,
+    resume: Option<Checkpoint>,
+) {
+    let volumes = extract_vops(document);
+    let camera = extract_camera(document, &volumes);
+    let surfaces = extract_surfaces(document, &volumes);
+    println!(
+        "Loaded: {} volume(s), {} surface(s).",
+        volumes.len(),
+        surfaces.len()
     );
+
+    let progressive = extract_progressive(document);
+    if let Some(stereo) = &camera.stereo {
+        if progressive.is_some() || checkpointer.is_some() {
+            panic!("Stereo rendering does not support progressive rendering or checkpoints.")
+        }
+        raytrace_stereo(&camera, stereo, &surfaces, filepath);
+    } else if progressive.is_none() && checkpointer.is_none() {
+        raytrace(&camera, &surfaces, filepath);
+    } else {
+        // a checkpointed regular render is a progressive render of a single pass
+        let settings = progressive.unwrap_or_else(Progressive::single_pass);
+        let pbar = progress_bar(&camera, settings.passes);
+        let result = render_progressive(
+            &camera,
+            &surfaces,
+            &settings,
+            filepath,
+            checkpointer,
+            resume,
+            |progress| pbar.set_position(progress.rays_done as u64),
+        );
+        pbar.finish_and_clear();
+
+        // the snapshots may have gone elsewhere
+        if settings.snapshot.is_some() {
+            save_image(filepath, &camera, result);
+        }
+    }
+    println!("Result saved: {}", filepath);
 }
 
 fn main() {
-    let document = load_from_yaml();
+    let contents = load_file();
+    let document: Mapping = from_str(&contents).expect("Error in parsing the file");
 
     // if a number of threads has been given, set it
     if let Some(x) = extract_threads(&document) {
@@ -150,15 +136,35 @@ fn main() {
     }
 
     let filepath = extract_filepath(&document);
-    let volumes = extract_vops(&document);
-    let camera = extract_camera(&document, &volumes);
-    let surfaces = extract_surfaces(&document, &volumes);
-    println!(
-        "Loaded: {} volume(s), {} surface(s).",
-        volumes.len(),
-        surfaces.len()
-    );
-
-    raytrace(&camera, &surfaces, &filepath);
-    println!("Result saved: {}", filepath);
+    let checkpointer = extract_checkpoint(&document).map(|settings| Checkpointer {
+        settings,
+        scene_hash: scene_hash(&contents),
+    });
+    let resume = if resume_requested() {
+        let checkpointer = checkpointer
+            .as_ref()
+            .expect("Cannot resume without checkpoint settings.");
+        println!("Resuming from checkpoint: {}", checkpointer.settings.path);
+        Some(checkpointer.resume())
+    } else {
+        None
+    };
+
+    match extract_animation(&document) {
+        None => render(&document, &filepath, checkpointer.as_ref(), resume),
+        Some(animation) => {
+            if checkpointer.is_some() {
+                panic!("Animations do not support checkpoints.")
+            }
+            for frame in 0..animation.frames {
+                println!("Rendering frame {}/{}.", frame + 1, animation.frames);
+                render(
+                    &animation.frame_document(&document, frame),
+                    &frame_path(&filepath, frame),
+                    None,
+                    None,
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,203 @@
+use {
+    crate::{
+        packet::{RayPacket, PACKET_SIZE},
+        surface::Hit,
+        Ray, Surface, VOP,
+    },
+    nalgebra::{Point3, Vector3},
+    std::sync::Arc,
+};
+
+/// Medium a ray has entered through a surface, along with the priority of that surface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Medium {
+    pub vop: Arc<VOP>,
+    pub priority: i32,
+}
+
+/// Media a ray is inside of, in the order it entered them. Where objects overlap, the ray travels
+/// through the medium of the one with the highest priority, the latest entered among equals. This
+/// lets a liquid fill a glass up to and into its walls, the glass taking over where they overlap.
+///
+/// Crossing a surface from the medium on one side to the medium on the other either leaves the
+/// first, if the ray entered it before, or enters the second. Surfaces bounding a medium
+/// overridden by one of higher priority are crossed without refraction.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediumStack {
+    entries: Vec<Medium>,
+    /// Number of surfaces met from a medium other than the one given for that side, which the
+    /// media entered cannot account for.
+    pub mismatches: usize,
+}
+
+impl MediumStack {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Medium the ray goes into when crossing a surface of the given priority from `from` to
+    /// `into`, or `None` if the surface is overridden and the ray should go straight through.
+    pub fn crossing(&self, from: &Arc<VOP>, into: &Arc<VOP>, priority: i32) -> Option<Arc<VOP>> {
+        match self.find(from, priority) {
+            // leaving a medium, into the one that remains or the other side if none does
+            Some(k) if self.current(None) == Some(k) => Some(
+                self.current(Some(k))
+                    .map_or_else(|| into.clone(), |m| self.entries[m].vop.clone()),
+            ),
+            Some(_) => None,
+            // entering a medium, unless one of higher priority is in the way
+            None if self
+                .current(None)
+                .is_some_and(|m| self.entries[m].priority > priority) =>
+            {
+                None
+            }
+            None => Some(into.clone()),
+        }
+    }
+
+    /// Record that the ray went through a surface of the given priority from `from` to `into`.
+    pub fn cross(&mut self, from: &Arc<VOP>, into: &Arc<VOP>, priority: i32) {
+        match self.find(from, priority) {
+            Some(k) => {
+                self.entries.remove(k);
+            }
+            None => self.entries.push(Medium {
+                vop: into.clone(),
+                priority,
+            }),
+        }
+    }
+
+    /// Latest entry of a medium entered through a surface of the given priority.
+    fn find(&self, vop: &Arc<VOP>, priority: i32) -> Option<usize> {
+        self.entries
+            .iter()
+            .rposition(|m| m.vop == *vop && m.priority == priority)
+    }
+
+    /// Entry the ray travels through, leaving out the given one.
+    fn current(&self, without: Option<usize>) -> Option<usize> {
+        let mut current: Option<usize> = None;
+        for (k, medium) in self.entries.iter().enumerate() {
+            if Some(k) != without
+                && current.is_none_or(|c| medium.priority >= self.entries[c].priority)
+            {
+                current = Some(k);
+            }
+        }
+        current
+    }
+}
+
+/// Surface taking priority over overlapping ones for the medium it bounds, see `MediumStack`.
+pub struct Prioritized {
+    surface: Arc<dyn Surface + Send + Sync>,
+    priority: i32,
+}
+
+impl Prioritized {
+    pub fn new(surface: Arc<dyn Surface + Send + Sync>, priority: i32) -> Self {
+        Self { surface, priority }
+    }
+}
+
+impl Surface for Prioritized {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let mut hit = self.surface.intersection(ray)?;
+        hit.priority = self.priority;
+        Some(hit)
+    }
+    fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        self.surface.packet_distances(packet)
+    }
+    fn sample_light(
+        &self,
+        from: &Point3<f64>,
+        time: f64,
+        u: [f64; 2],
+    ) -> Option<(Vector3<f64>, f64)> {
+        self.surface.sample_light(from, time, u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            surface::sphere::{simple::Sphere, SphereShape},
+            SOP,
+        },
+    };
+
+    fn vop(ior: f64) -> Arc<VOP> {
+        Arc::new(VOP::new(ior, [0.0; 3]))
+    }
+
+    fn sphere(radius: f64, vop_above: &Arc<VOP>, vop_below: &Arc<VOP>) -> Sphere {
+        Sphere {
+            geometry: SphereShape::new(Point3::origin(), radius, None, None),
+            sop: SOP::Refract,
+            vop_above: vop_above.clone(),
+            vop_below: vop_below.clone(),
+        }
+    }
+
+    /// Index of refraction of the medium the ray is in after each surface it goes through.
+    fn iors(surfaces: &[Arc<dyn Surface + Send + Sync>], ray: &mut Ray) -> Vec<f64> {
+        let mut iors = Vec::new();
+        while let Some(hit) = surfaces
+            .iter()
+            .filter_map(|s| s.intersection(ray))
+            .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())
+        {
+            ray.bounce(&hit);
+            iors.push(ray.vop.ior);
+        }
+        iors
+    }
+
+    fn ray(vop: &Arc<VOP>) -> Ray {
+        Ray {
+            origin: Point3::new(-5.0, 0.0, 0.0),
+            direction: Vector3::x(),
+            vop: vop.clone(),
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        }
+    }
+
+    /// Water overlapping the walls of a glass bowl fills its cavity, the glass taking over in the
+    /// walls.
+    #[test]
+    fn liquid_in_glass() {
+        let (air, glass, water) = (vop(1.0), vop(1.5), vop(1.33));
+        let surfaces: Vec<Arc<dyn Surface + Send + Sync>> = vec![
+            Arc::new(Prioritized::new(Arc::new(sphere(2.0, &air, &glass)), 1)),
+            Arc::new(Prioritized::new(Arc::new(sphere(1.5, &glass, &air)), 1)),
+            Arc::new(sphere(1.6, &air, &water)),
+        ];
+        let mut ray = ray(&air);
+        assert_eq!(
+            iors(&surfaces, &mut ray),
+            vec![1.5, 1.5, 1.33, 1.5, 1.5, 1.0]
+        );
+        assert!(ray.direction.normalize().x > 1.0 - crate::TOLERANCE);
+        assert_eq!(ray.media, MediumStack::default());
+    }
+
+    /// Surfaces met from a medium other than the one given for that side are counted, and the ray
+    /// carries on from the medium it is in.
+    #[test]
+    fn medium_mismatch() {
+        let (air, glass, water) = (vop(1.0), vop(1.5), vop(1.33));
+        let surfaces: Vec<Arc<dyn Surface + Send + Sync>> =
+            vec![Arc::new(sphere(1.0, &water, &glass))];
+        let mut ray = ray(&air);
+        assert_eq!(iors(&surfaces, &mut ray), vec![1.5, 1.33]);
+        assert_eq!(ray.media.mismatches, 1);
+    }
+}
@@ -0,0 +1,197 @@
+use {
+    crate::{surface::Hit, Ray, Surface},
+    nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3},
+    serde::Deserialize,
+    std::sync::Arc,
+};
+
+/// Interval during which the camera shutter is open. Every sample gets a random moment within
+/// it, so that moving surfaces are blurred along their motion.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Shutter {
+    #[serde(default)]
+    pub open: f64,
+    pub close: f64,
+}
+
+impl Shutter {
+    /// Moment at a given fraction of the interval.
+    pub fn time(&self, fraction: f64) -> f64 {
+        self.open + fraction * (self.close - self.open)
+    }
+}
+
+/// Placement of a surface at a given time, relative to where it is described in the scene.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Keyframe {
+    pub time: f64,
+    #[serde(default)]
+    pub translation: [f64; 3],
+    /// Rotation about the pivot, as roll, pitch and yaw angles in degrees.
+    #[serde(default)]
+    pub rotation: [f64; 3],
+}
+
+/// Motion of a surface, either at a constant velocity from its place at time 0 or through
+/// keyframes, linearly interpolated and held before the first and after the last.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Motion {
+    velocity: Option<[f64; 3]>,
+    #[serde(default)]
+    keyframes: Vec<Keyframe>,
+    /// Point that keyframe rotations turn about.
+    #[serde(default)]
+    pivot: [f64; 3],
+}
+
+impl Motion {
+    /// Transform from the place of the surface in the scene to its place at the given time.
+    pub fn transform(&self, time: f64) -> Isometry3<f64> {
+        match (self.velocity, self.keyframes.as_slice()) {
+            (Some(velocity), []) => Translation3::from(Vector3::from(velocity) * time).into(),
+            (None, [_, ..]) => {
+                let times: Vec<f64> = self.keyframes.iter().map(|k| k.time).collect();
+                let (a, b, s) = segment(&times, time);
+                let (a, b) = (&self.keyframes[a], &self.keyframes[b]);
+                let translation =
+                    Vector3::from(a.translation).lerp(&Vector3::from(b.translation), s);
+                let rotation = keyframe_rotation(a).slerp(&keyframe_rotation(b), s);
+                let pivot = Vector3::from(self.pivot);
+                Translation3::from(pivot + translation) * rotation * Translation3::from(-pivot)
+            }
+            _ => panic!("Motion must be given either as a velocity or as keyframes."),
+        }
+    }
+
+    /// Check that the motion is well defined, with keyframes in order of time.
+    fn validate(&self) {
+        if self
+            .keyframes
+            .windows(2)
+            .any(|pair| pair[1].time < pair[0].time)
+        {
+            panic!("Motion keyframes must be given in order of time.")
+        }
+        self.transform(0.0);
+    }
+}
+
+/// Keyframes around a given time, from the times of all keyframes in order, along with the
+/// fraction of the way from the first to the second. Times before the first keyframe and after
+/// the last hold it.
+pub fn segment(times: &[f64], time: f64) -> (usize, usize, f64) {
+    match times.iter().rposition(|&t| t <= time) {
+        None => (0, 0, 0.0),
+        Some(k) if k + 1 < times.len() => (k, k + 1, (time - times[k]) / (times[k + 1] - times[k])),
+        Some(k) => (k, k, 0.0),
+    }
+}
+
+fn keyframe_rotation(keyframe: &Keyframe) -> UnitQuaternion<f64> {
+    let [roll, pitch, yaw] = keyframe.rotation.map(f64::to_radians);
+    UnitQuaternion::from_euler_angles(roll, pitch, yaw)
+}
+
+/// Surface following a motion. Rays are intersected with the surface where it is at the time
+/// they travel.
+pub struct Moving {
+    surface: Arc<dyn Surface + Send + Sync>,
+    motion: Motion,
+}
+
+impl Moving {
+    pub fn new(surface: Arc<dyn Surface + Send + Sync>, motion: Motion) -> Self {
+        motion.validate();
+        Self { surface, motion }
+    }
+}
+
+impl Surface for Moving {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        // move the ray instead of the surface, then the hit back along with the surface
+        let transform = self.motion.transform(ray.time);
+        let mut local = ray.clone();
+        local.origin = transform.inverse_transform_point(&ray.origin);
+        local.direction = transform.inverse_transform_vector(&ray.direction);
+        let mut hit = self.surface.intersection(&local)?;
+        hit.point = transform * hit.point;
+        hit.normal = transform * hit.normal;
+        Some(hit)
+    }
+    fn sample_light(
+        &self,
+        from: &Point3<f64>,
+        time: f64,
+        u: [f64; 2],
+    ) -> Option<(Vector3<f64>, f64)> {
+        let transform = self.motion.transform(time);
+        let (direction, density) =
+            self.surface
+                .sample_light(&transform.inverse_transform_point(from), time, u)?;
+        Some((transform * direction, density))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            surface::sphere::{simple::Sphere, SphereShape},
+            SOP, TOLERANCE, VOP,
+        },
+    };
+
+    fn sphere(motion: &str) -> Moving {
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
+        let sphere = Sphere {
+            geometry: SphereShape::new(Point3::origin(), 1.0, None, None),
+            sop: SOP::Dark,
+            vop_above: air.clone(),
+            vop_below: air,
+        };
+        Moving::new(Arc::new(sphere), serde_yaml::from_str(motion).unwrap())
+    }
+
+    fn ray(time: f64) -> Ray {
+        Ray {
+            origin: Point3::new(3.0, -5.0, 0.0),
+            direction: Vector3::y(),
+            vop: Arc::new(VOP::new(1.0, [0.0; 3])),
+            abs: [0.0; 3],
+            time,
+            media: Default::default(),
+            path: Default::default(),
+        }
+    }
+
+    /// Rays must hit the surface where it has moved to at their time.
+    #[test]
+    fn velocity_moves_surface() {
+        let moving = sphere("{velocity: [2, 0, 0]}");
+        assert!(moving.intersection(&ray(0.0)).is_none());
+        let hit = moving.intersection(&ray(1.5)).unwrap();
+        assert!((hit.point - Point3::new(3.0, -1.0, 0.0)).norm() < TOLERANCE);
+        assert!((hit.normal.into_inner() + Vector3::y()).norm() < TOLERANCE);
+        assert!((hit.t - 4.0).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn keyframes_interpolate() {
+        let motion: Motion = serde_yaml::from_str(
+            "{pivot: [1, 0, 0], keyframes: [\
+             {time: 1, translation: [0, 0, 0]}, \
+             {time: 3, translation: [4, 0, 0], rotation: [0, 0, 90]}]}",
+        )
+        .unwrap();
+        let point = Point3::new(2.0, 0.0, 0.0);
+        // held before the first keyframe and after the last
+        assert!((motion.transform(0.0) * point - point).norm() < TOLERANCE);
+        let end = Point3::new(5.0, 1.0, 0.0);
+        assert!((motion.transform(5.0) * point - end).norm() < TOLERANCE);
+        // halfway, half the translation and half the rotation about the pivot
+        let half = 0.5f64.sqrt();
+        let middle = Point3::new(3.0 + half, half, 0.0);
+        assert!((motion.transform(2.0) * point - middle).norm() < TOLERANCE);
+    }
+}
@@ -0,0 +1,283 @@
+use {
+    crate::{
+        ray::{BounceLimit, BounceResult},
+        Ray, Surface,
+    },
+    std::sync::Arc,
+};
+
+/// Number of rays traced together in a packet.
+pub const PACKET_SIZE: usize = 4;
+
+/// A bundle of rays laid out component by component, so that intersection tests can run on all
+/// lanes at once. Packets built from fewer than `PACKET_SIZE` rays pad the missing lanes with
+/// copies of the last ray, whose results are then ignored.
+pub struct RayPacket<'a> {
+    pub rays: &'a [Ray],
+    pub origin: [[f64; PACKET_SIZE]; 3],
+    pub direction: [[f64; PACKET_SIZE]; 3],
+}
+
+impl<'a> RayPacket<'a> {
+    pub fn new(rays: &'a [Ray]) -> Self {
+        if rays.is_empty() || rays.len() > PACKET_SIZE {
+            panic!("A ray packet must hold between 1 and {} rays.", PACKET_SIZE)
+        }
+        let mut origin = [[0.0; PACKET_SIZE]; 3];
+        let mut direction = [[0.0; PACKET_SIZE]; 3];
+        for lane in 0..PACKET_SIZE {
+            let ray = &rays[lane.min(rays.len() - 1)];
+            for axis in 0..3 {
+                origin[axis][lane] = ray.origin[axis];
+                direction[axis][lane] = ray.direction[axis];
+            }
+        }
+        Self {
+            rays,
+            origin,
+            direction,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rays.is_empty()
+    }
+}
+
+/// Dot product of every lane of a packet vector with a single vector.
+pub fn lane_dot(lanes: &[[f64; PACKET_SIZE]; 3], vector: &[f64; 3]) -> [f64; PACKET_SIZE] {
+    let mut result = [0.0; PACKET_SIZE];
+    for (axis, component) in lanes.iter().enumerate() {
+        for (r, c) in result.iter_mut().zip(component.iter()) {
+            *r += c * vector[axis];
+        }
+    }
+    result
+}
+
+/// Launch a packet of rays through the system. The first intersection of all rays is found
+/// packet-wise, after which each ray bounces and continues on its own.
+pub fn launch_packet(
+    rays: &mut [Ray],
+    surfaces: &[Arc<dyn Surface + Send + Sync>],
+    limit: &BounceLimit,
+) -> Vec<BounceResult> {
+    // rays stopped before their first bounce need no intersection at all
+    if limit.max == 0 {
+        return rays
+            .iter_mut()
+            .map(|ray| ray.launch(surfaces, limit))
+            .collect();
+    }
+
+    // closest surface along each lane
+    let mut closest_t = [f64::INFINITY; PACKET_SIZE];
+    let mut closest_index = [usize::MAX; PACKET_SIZE];
+    {
+        let packet = RayPacket::new(rays);
+        for (index, surface) in surfaces.iter().enumerate() {
+            let distances = surface.packet_distances(&packet);
+            for lane in 0..PACKET_SIZE {
+                if distances[lane] < closest_t[lane] {
+                    closest_t[lane] = distances[lane];
+                    closest_index[lane] = index;
+                }
+            }
+        }
+    }
+
+    rays.iter_mut()
+        .zip(closest_index.iter())
+        .map(|(ray, &index)| {
+            // rays that may scatter before the first surface go on their own
+            if ray.vop.scatters() {
+                return ray.launch(surfaces, limit);
+            }
+            // no surface in the way
+            if index == usize::MAX {
+                return BounceResult::Kill;
+            }
+            // the packet test only gives distances, so fetch the full hit from the winning
+            // surface and fall back to the scalar path if rounding made the hit disappear
+            match surfaces[index].intersection(ray) {
+                Some(hit) => match ray.bounce(&hit) {
+                    BounceResult::Continue => ray.launch_after(surfaces, limit, 1),
+                    BounceResult::Error => panic!("Something went wrong!"),
+                    br => br,
+                },
+                None => ray.launch(surfaces, limit),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            ray::LimitResult,
+            surface::{
+                plane::{simple::Plane, PlaneShape},
+                sphere::{simple::Sphere, SphereShape},
+            },
+            SOP, VOP,
+        },
+        nalgebra::{Point3, Vector3},
+        std::sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    fn air() -> Arc<VOP> {
+        Arc::new(VOP::new(1.0, [0.0; 3]))
+    }
+
+    fn scene(air: Arc<VOP>) -> Vec<Arc<dyn Surface + Send + Sync>> {
+        vec![
+            Arc::new(Plane {
+                geometry: PlaneShape::new(Point3::new(0.0, 0.0, -5.0), Vector3::z(), None),
+                sop: SOP::Light(255, 255, 255),
+                vop_above: air.clone(),
+                vop_below: air.clone(),
+            }),
+            Arc::new(Sphere {
+                geometry: SphereShape::new(Point3::new(0.0, 0.0, -2.0), 1.0, None, None),
+                sop: SOP::Light(255, 0, 0),
+                vop_above: air.clone(),
+                vop_below: air,
+            }),
+        ]
+    }
+
+    fn rays_along(air: Arc<VOP>, directions: &[Vector3<f64>]) -> Vec<Ray> {
+        directions
+            .iter()
+            .map(|direction| Ray {
+                origin: Point3::origin(),
+                direction: *direction,
+                vop: air.clone(),
+                abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
+            })
+            .collect()
+    }
+
+    fn rays(air: Arc<VOP>) -> Vec<Ray> {
+        rays_along(
+            air,
+            &[
+                Vector3::new(0.0, 0.0, -1.0),
+                Vector3::new(0.5, 0.0, -1.0),
+                Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.1, -0.2, -1.0),
+                Vector3::new(2.0, 1.0, -1.0),
+            ],
+        )
+    }
+
+    /// Packets must give the same result as launching each ray on its own.
+    #[test]
+    fn packet_matches_scalar() {
+        let air = air();
+        let scene = scene(air.clone());
+        let mut scalar = rays(air.clone());
+        let expected: Vec<BounceResult> = scalar
+            .iter_mut()
+            .map(|r| r.launch(&scene, &BounceLimit::default()))
+            .collect();
+
+        let mut packed = rays(air);
+        let result: Vec<BounceResult> = packed
+            .chunks_mut(PACKET_SIZE)
+            .flat_map(|chunk| launch_packet(chunk, &scene, &BounceLimit::default()))
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn padded_packet() {
+        let air = air();
+        let rays = rays(air);
+        let packet = RayPacket::new(&rays[4..]);
+        assert_eq!(packet.len(), 1);
+        assert_eq!(packet.direction[0], [2.0; PACKET_SIZE]);
+    }
+
+    /// Packets and single rays must stop at the same number of bounces: a ray straight into a
+    /// light needs no bounce to reach it, a ray off a mirror in front of it needs one.
+    #[test]
+    fn bounce_limit() {
+        let air = air();
+        let scene: Vec<Arc<dyn Surface + Send + Sync>> = vec![
+            Arc::new(Plane {
+                geometry: PlaneShape::new(Point3::new(0.0, 0.0, 5.0), Vector3::z(), None),
+                sop: SOP::Light(255, 255, 255),
+                vop_above: air.clone(),
+                vop_below: air.clone(),
+            }),
+            Arc::new(Plane {
+                geometry: PlaneShape::new(Point3::new(0.0, 0.0, -2.0), Vector3::z(), None),
+                sop: SOP::Reflect,
+                vop_above: air.clone(),
+                vop_below: air.clone(),
+            }),
+        ];
+        let directions = [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0)];
+        let expected = [
+            [BounceResult::Limit(1, 2, 3), BounceResult::Limit(1, 2, 3)],
+            [
+                BounceResult::Count(255, 255, 255),
+                BounceResult::Limit(1, 2, 3),
+            ],
+            [
+                BounceResult::Count(255, 255, 255),
+                BounceResult::Count(255, 255, 255),
+            ],
+        ];
+        for (max, expected) in expected.iter().enumerate() {
+            let limit = BounceLimit {
+                max,
+                result: LimitResult::Debug(1, 2, 3),
+            };
+            let scalar: Vec<BounceResult> = rays_along(air.clone(), &directions)
+                .iter_mut()
+                .map(|r| r.launch(&scene, &limit))
+                .collect();
+            assert_eq!(scalar, expected, "single rays, max {}", max);
+            let packed = launch_packet(&mut rays_along(air.clone(), &directions), &scene, &limit);
+            assert_eq!(packed, expected, "packet, max {}", max);
+        }
+    }
+
+    /// Surface that no packet ever hits, counting the rays intersected with it one by one.
+    struct Missed(AtomicUsize);
+
+    impl Surface for Missed {
+        fn intersection(&self, _ray: &Ray) -> Option<crate::surface::Hit<'_>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            None
+        }
+
+        fn packet_distances(&self, _packet: &RayPacket) -> [f64; PACKET_SIZE] {
+            [f64::INFINITY; PACKET_SIZE]
+        }
+    }
+
+    /// Rays that miss every surface of the packet test must be killed without being traced again
+    /// on their own.
+    #[test]
+    fn missed_packet() {
+        let missed = Arc::new(Missed(AtomicUsize::new(0)));
+        let scene: Vec<Arc<dyn Surface + Send + Sync>> = vec![missed.clone(), missed.clone()];
+        let mut rays = rays(air());
+        let result = launch_packet(&mut rays[..PACKET_SIZE], &scene, &BounceLimit::default());
+        assert_eq!(result.len(), PACKET_SIZE);
+        assert!(result.iter().all(|r| *r == BounceResult::Kill));
+        assert_eq!(missed.0.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,379 @@
+use {
+    crate::{
+        camera::{save_image, trace_tiles, Camera, Progress, Tile},
+        checkpoint::{Checkpoint, Checkpointer},
+        Surface,
+    },
+    serde::{Deserialize, Serialize},
+    std::fs,
+    std::sync::atomic::{AtomicUsize, Ordering},
+    std::sync::{Arc, Mutex},
+    std::time::{Duration, Instant},
+};
+
+/// Settings of a progressive render. Every pass traces `antialiasing`^2 new samples per pixel,
+/// after which the running average is saved to the snapshot path. Rendering stops at whichever
+/// of the pass count, time limit or noise target comes first; the time limit is checked between
+/// passes, so the last pass may overrun it.
+#[derive(Debug, Deserialize)]
+pub struct Progressive {
+    #[serde(default = "default_passes")]
+    pub passes: usize,
+    /// Time budget in seconds.
+    pub time_limit: Option<f64>,
+    /// Target mean standard error of the pixel values, in 0-255 color units.
+    pub noise: Option<f64>,
+    /// Where to write the intermediate images, if not to the final output path.
+    pub snapshot: Option<String>,
+}
+
+fn default_passes() -> usize {
+    16
+}
+
+impl Progressive {
+    /// Settings for a regular render, done in a single pass.
+    pub fn single_pass() -> Self {
+        Self {
+            passes: 1,
+            time_limit: None,
+            noise: None,
+            snapshot: None,
+        }
+    }
+}
+
+/// Sample position inside the subpixels for a given pass, following the R2 low-discrepancy
+/// sequence so that successive passes cover the subpixels evenly. The first pass samples the
+/// subpixel centers, like a regular render.
+pub fn pass_offset(pass: usize) -> [f64; 2] {
+    // plastic number, the generalized golden ratio in two dimensions
+    const G: f64 = 1.324_717_957_244_746;
+    [
+        (0.5 + pass as f64 / G).fract(),
+        (0.5 + pass as f64 / (G * G)).fract(),
+    ]
+}
+
+/// Accumulation of the passes of a progressive render. Pixels are added tile by tile, so in the
+/// middle of a pass some pixels have one more sample than others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Film {
+    passes: usize,
+    sum: Vec<[f64; 3]>,
+    sum_squares: Vec<[f64; 3]>,
+    counts: Vec<u32>,
+}
+
+impl Film {
+    pub fn new(num_pixels: usize) -> Self {
+        Self {
+            passes: 0,
+            sum: vec![[0.0; 3]; num_pixels],
+            sum_squares: vec![[0.0; 3]; num_pixels],
+            counts: vec![0; num_pixels],
+        }
+    }
+
+    /// Number of complete passes.
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+
+    pub fn num_pixels(&self) -> usize {
+        self.sum.len()
+    }
+
+    fn add_pixel(&mut self, index: usize, pixel: &[f64; 3]) {
+        for (n, value) in pixel.iter().enumerate() {
+            self.sum[index][n] += value;
+            self.sum_squares[index][n] += value.powi(2);
+        }
+        self.counts[index] += 1;
+    }
+
+    /// Add the pixels of a tile, row by row, to a film covering the given window.
+    pub fn add_tile(&mut self, tile: &Tile, pixels: &[[f64; 3]], window: &Tile) {
+        for (k, pixel) in pixels.iter().enumerate() {
+            self.add_pixel(tile.index_in(window, k), pixel);
+        }
+    }
+
+    /// Mark the pass in progress as complete.
+    pub fn finish_pass(&mut self) {
+        self.passes += 1;
+    }
+
+    /// Add a whole pass at once.
+    pub fn add_pass(&mut self, pass: &[[f64; 3]]) {
+        for (index, pixel) in pass.iter().enumerate() {
+            self.add_pixel(index, pixel);
+        }
+        self.finish_pass();
+    }
+
+    /// Current average of every pixel.
+    pub fn average(&self) -> Vec<[u8; 3]> {
+        self.sum
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(sum, &count)| sum.map(|s| (s / count.max(1) as f64) as u8))
+            .collect()
+    }
+
+    /// Estimated noise of the image, as the standard error of the pixel averages, taken over all
+    /// pixels and channels. Needs at least two complete passes.
+    pub fn noise(&self) -> Option<f64> {
+        if self.passes < 2 || self.sum.is_empty() {
+            return None;
+        }
+        let total: f64 = self
+            .sum
+            .iter()
+            .zip(self.sum_squares.iter())
+            .zip(self.counts.iter())
+            .flat_map(|((sum, sum_squares), &count)| {
+                let n = count as f64;
+                sum.iter().zip(sum_squares.iter()).map(move |(s, s2)| {
+                    let variance = ((s2 - s * s / n) / (n - 1.0)).max(0.0);
+                    (variance / n).sqrt()
+                })
+            })
+            .sum();
+        Some(total / (3 * self.sum.len()) as f64)
+    }
+}
+
+/// Render the image in successive passes, saving a snapshot of the running average after every
+/// pass, and return the final image. With a checkpointer, the state of the render is saved at
+/// regular intervals and after every pass, and a previous state can be resumed from. Progress is
+/// reported per tile, as in `trace_rays`, over all the passes to render.
+pub fn render_progressive<F>(
+    camera: &Camera,
+    scene: &[Arc<dyn Surface + Send + Sync>],
+    settings: &Progressive,
+    filepath: &str,
+    checkpointer: Option<&Checkpointer>,
+    resume: Option<Checkpoint>,
+    on_progress: F,
+) -> Vec<[u8; 3]>
+where
+    F: Fn(&Progress) + Sync,
+{
+    let snapshot = settings.snapshot.as_deref().unwrap_or(filepath);
+    let tiles = camera.tiles();
+    let num_pixels = camera.window.num_pixels();
+    let rays_per_pixel = camera.antialiasing.pow(2);
+    let mut state = resume.unwrap_or_else(|| Checkpoint {
+        scene_hash: checkpointer.map_or(0, |c| c.scene_hash),
+        film: Film::new(num_pixels),
+        tiles_done: vec![false; tiles.len()],
+        rays_traced: 0,
+        bounce_limited: 0,
+        medium_mismatches: 0,
+        elapsed: 0.0,
+    });
+    if state.film.num_pixels() != num_pixels || state.tiles_done.len() != tiles.len() {
+        panic!("Checkpoint does not match the camera resolution.")
+    }
+
+    // time spent before resuming counts towards the time limit
+    let t0 = Instant::now();
+    let elapsed_before = state.elapsed;
+    let elapsed = || elapsed_before + t0.elapsed().as_secs_f64();
+    let finished = |film: &Film, seconds: f64| {
+        film.passes() >= settings.passes
+            || settings.time_limit.is_some_and(|limit| seconds >= limit)
+            || film
+                .noise()
+                .is_some_and(|n| settings.noise.is_some_and(|target| n <= target))
+    };
+
+    println!("Starting progressive raytrace...");
+    while !finished(&state.film, elapsed()) {
+        // tiles already done in a resumed pass are skipped
+        let todo: Vec<usize> = (0..tiles.len()).filter(|&i| !state.tiles_done[i]).collect();
+        let todo_tiles: Vec<Tile> = todo.iter().map(|&i| tiles[i].clone()).collect();
+        let pass = state.film.passes();
+        let pixels_todo: usize = todo_tiles.iter().map(Tile::num_pixels).sum();
+        let rays_done = AtomicUsize::new(((pass + 1) * num_pixels - pixels_todo) * rays_per_pixel);
+
+        let shared = Mutex::new((state, Instant::now()));
+        // render time of the last checkpoint written, so that an older one never overwrites it
+        let written = Mutex::new(0.0);
+        trace_tiles(camera, scene, pass, &todo_tiles, |i, pixels, count| {
+            // checkpoints are copied under the lock, but written outside of it so that the other
+            // workers keep adding their tiles
+            let due = {
+                let (state, last_save) = &mut *shared.lock().unwrap();
+                state.film.add_tile(&todo_tiles[i], &pixels, &camera.window);
+                state.tiles_done[todo[i]] = true;
+                state.rays_traced += count.traced;
+                state.bounce_limited += count.bounce_limited;
+                state.medium_mismatches += count.medium_mismatches;
+                checkpointer
+                    .filter(|c| last_save.elapsed().as_secs_f64() >= c.settings.interval)
+                    .map(|c| {
+                        state.elapsed = elapsed();
+                        *last_save = Instant::now();
+                        (c, state.clone())
+                    })
+            };
+            if let Some((checkpointer, checkpoint)) = due {
+                let mut written = written.lock().unwrap();
+                if checkpoint.elapsed > *written {
+                    checkpoint.save(&checkpointer.settings.path);
+                    *written = checkpoint.elapsed;
+                }
+            }
+
+            let tile_rays = todo_tiles[i].num_pixels() * rays_per_pixel;
+            on_progress(&Progress {
+                rays_done: rays_done.fetch_add(tile_rays, Ordering::Relaxed) + tile_rays,
+                rays_total: settings.passes * num_pixels * rays_per_pixel,
+                elapsed: Duration::from_secs_f64(elapsed()),
+            });
+        });
+        state = shared.into_inner().unwrap().0;
+
+        state.film.finish_pass();
+        state.tiles_done.iter_mut().for_each(|done| *done = false);
+        save_image(snapshot, camera, state.film.average());
+        if let Some(checkpointer) = checkpointer {
+            state.elapsed = elapsed();
+            state.save(&checkpointer.settings.path);
+        }
+
+        let noise = state.film.noise();
+        println!(
+            "Pass {} done: {:.2} sample(s)/pixel, noise: {}, total time: {:.3}s.",
+            state.film.passes(),
+            state.rays_traced as f64 / num_pixels as f64,
+            noise.map_or_else(|| "-".to_owned(), |n| format!("{:.3}", n)),
+            elapsed(),
+        );
+    }
+    if state.bounce_limited > 0 {
+        println!(
+            "{} ray(s) stopped after {} bounces.",
+            state.bounce_limited, camera.bounce_limit.max
+        );
+    }
+    if state.medium_mismatches > 0 {
+        println!(
+            "Warning: {} ray(s) met a surface from a medium other than the one given for that side.",
+            state.medium_mismatches
+        );
+    }
+
+    // the render is complete, nothing left to resume
+    if let Some(checkpointer) = checkpointer {
+        let _ = fs::remove_file(&checkpointer.settings.path);
+    }
+    state.film.average()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::camera::tests::scene_camera};
+
+    fn scene() -> (Camera, Vec<Arc<dyn Surface + Send + Sync>>) {
+        scene_camera("{fov: [40, 40], density: 1, antialiasing: 2}")
+    }
+
+    fn settings(passes: usize) -> Progressive {
+        Progressive {
+            passes,
+            ..Progressive::single_pass()
+        }
+    }
+
+    /// Resuming from the middle of a pass must give the same image as an uninterrupted render.
+    #[test]
+    fn resume_matches_uninterrupted() {
+        let (camera, scene) = scene();
+        let path =
+            std::env::temp_dir().join(format!("raytracer-resume-{}.jpg", std::process::id()));
+        let path = path.to_str().unwrap();
+        let expected = render_progressive(&camera, &scene, &settings(2), path, None, None, |_| {});
+
+        // interrupted halfway through the first pass
+        let tiles = camera.tiles();
+        let mut checkpoint = Checkpoint {
+            scene_hash: 0,
+            film: Film::new(camera.window.num_pixels()),
+            tiles_done: vec![false; tiles.len()],
+            rays_traced: 0,
+            bounce_limited: 0,
+            medium_mismatches: 0,
+            elapsed: 0.0,
+        };
+        let half = tiles.len() / 2;
+        let shared = Mutex::new(&mut checkpoint);
+        trace_tiles(&camera, &scene, 0, &tiles[..half], |i, pixels, _| {
+            let mut checkpoint = shared.lock().unwrap();
+            checkpoint.film.add_tile(&tiles[i], &pixels, &camera.window);
+            checkpoint.tiles_done[i] = true;
+        });
+
+        let resumed = render_progressive(
+            &camera,
+            &scene,
+            &settings(2),
+            path,
+            None,
+            Some(checkpoint),
+            |_| {},
+        );
+        fs::remove_file(path).unwrap();
+        assert_eq!(resumed, expected);
+    }
+
+    /// Progress must run over all the passes, up to every ray of the render.
+    #[test]
+    fn progress_over_all_passes() {
+        let (camera, scene) = scene();
+        let path =
+            std::env::temp_dir().join(format!("raytracer-progress-{}.jpg", std::process::id()));
+        let path = path.to_str().unwrap();
+        let rays_total = 3 * camera.window.num_pixels() * 4;
+        let last = AtomicUsize::new(0);
+        render_progressive(
+            &camera,
+            &scene,
+            &settings(3),
+            path,
+            None,
+            None,
+            |progress| {
+                assert_eq!(progress.rays_total, rays_total);
+                last.fetch_max(progress.rays_done, Ordering::Relaxed);
+            },
+        );
+        fs::remove_file(path).unwrap();
+        assert_eq!(last.into_inner(), rays_total);
+    }
+
+    #[test]
+    fn first_pass_at_centers() {
+        assert_eq!(pass_offset(0), [0.5, 0.5]);
+        for pass in 1..100 {
+            let offset = pass_offset(pass);
+            assert!(offset.iter().all(|o| (0.0..1.0).contains(o)));
+            assert_ne!(offset, pass_offset(pass - 1));
+        }
+    }
+
+    #[test]
+    fn film_average_and_noise() {
+        let mut film = Film::new(2);
+        assert_eq!(film.noise(), None);
+        film.add_pass(&[[10.0, 20.0, 30.0], [0.0; 3]]);
+        film.add_pass(&[[20.0, 20.0, 30.0], [0.0; 3]]);
+        assert_eq!(film.average(), vec![[15, 20, 30], [0, 0, 0]]);
+
+        // only one channel of one pixel varies, with a standard error of 5
+        let noise = film.noise().unwrap();
+        assert!((noise - 5.0 / 6.0).abs() <= 1e-9);
+    }
+}
@@ -0,0 +1,176 @@
+use {serde::Deserialize, std::f64::consts::PI};
+
+/// Mapping of the pixels of the screen to rays.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    /// Rays from the camera origin through a screen one unit ahead, spanning the field of view.
+    #[default]
+    Perspective,
+    /// Rays along the gaze, from a screen centered on the camera origin.
+    Orthographic,
+    /// Rows of pixels at even steps of latitude, columns at even steps of longitude.
+    Equirectangular,
+    /// Distance from the image center growing with the angle from the gaze, following the
+    /// fisheye mapping.
+    Fisheye,
+}
+
+/// Relation between the angle of a ray from the gaze and its distance from the image center.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle.
+    #[default]
+    Equidistant,
+    /// Equal areas of the image for equal solid angles.
+    Equisolid,
+    /// Conformal mapping, preserving shapes locally.
+    Stereographic,
+}
+
+impl FisheyeMapping {
+    /// Distance from the image center, for a focal length of 1.
+    pub fn radius(&self, angle: f64) -> f64 {
+        match self {
+            FisheyeMapping::Equidistant => angle,
+            FisheyeMapping::Equisolid => 2.0 * (angle / 2.0).sin(),
+            FisheyeMapping::Stereographic => 2.0 * (angle / 2.0).tan(),
+        }
+    }
+
+    /// Angle from the gaze at a given distance from the image center, if the mapping reaches it.
+    pub fn angle(&self, radius: f64) -> Option<f64> {
+        let angle = match self {
+            FisheyeMapping::Equidistant => radius,
+            FisheyeMapping::Equisolid if radius <= 2.0 => 2.0 * (radius / 2.0).asin(),
+            FisheyeMapping::Equisolid => return None,
+            FisheyeMapping::Stereographic => 2.0 * (radius / 2.0).atan(),
+        };
+        Some(angle).filter(|&a| a <= PI)
+    }
+
+    /// Largest field of view the mapping can cover, in degrees.
+    pub fn max_fov(&self) -> f64 {
+        match self {
+            FisheyeMapping::Stereographic => 360.0 - f64::EPSILON,
+            _ => 360.0,
+        }
+    }
+}
+
+/// Direction of the ray at a point of an angular projection, as components along the gaze, up
+/// and right directions of the camera. The point is given in radians from the top left corner of
+/// an image spanning `fov` radians, rows first. Fisheye images span the field of view of their
+/// longer side, the shorter side following at the same image scale, and points outside the image
+/// circle, of diameter the larger field of view, have no ray.
+pub fn angular_direction(
+    projection: Projection,
+    mapping: FisheyeMapping,
+    fov: [f64; 2],
+    point: [f64; 2],
+) -> Option<[f64; 3]> {
+    // angles up from and right of the image center
+    let (up, right) = (fov[0] / 2.0 - point[0], point[1] - fov[1] / 2.0);
+    match projection {
+        Projection::Equirectangular => {
+            let (latitude, longitude) = (up, right);
+            Some([
+                latitude.cos() * longitude.cos(),
+                latitude.sin(),
+                latitude.cos() * longitude.sin(),
+            ])
+        }
+        Projection::Fisheye => {
+            // position in the image plane, at the same scale along both sides so that circles
+            // stay round, the edges of the longer side at its half field of view
+            let half_fov = fov[0].max(fov[1]) / 2.0;
+            let scale = mapping.radius(half_fov) / half_fov;
+            let position = [up * scale, right * scale];
+            let radius = position[0].hypot(position[1]);
+            if radius == 0.0 {
+                return Some([1.0, 0.0, 0.0]);
+            }
+            let angle = mapping.angle(radius).filter(|&a| a <= half_fov + 1e-12)?;
+            Some([
+                angle.cos(),
+                angle.sin() * position[0] / radius,
+                angle.sin() * position[1] / radius,
+            ])
+        }
+        _ => panic!("Projection {:?} is not angular.", projection),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::TOLERANCE};
+
+    fn close(a: [f64; 3], b: [f64; 3]) -> bool {
+        (0..3).all(|n| (a[n] - b[n]).abs() < TOLERANCE)
+    }
+
+    #[test]
+    fn equirectangular_directions() {
+        let fov = [PI, 2.0 * PI];
+        let direction = |point| {
+            angular_direction(
+                Projection::Equirectangular,
+                FisheyeMapping::Equidistant,
+                fov,
+                point,
+            )
+            .unwrap()
+        };
+        assert!(close(direction([PI / 2.0, PI]), [1.0, 0.0, 0.0]));
+        assert!(close(direction([0.0, PI]), [0.0, 1.0, 0.0]));
+        assert!(close(direction([PI / 2.0, 1.5 * PI]), [0.0, 0.0, 1.0]));
+        assert!(close(direction([PI / 2.0, 0.0]), [-1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn fisheye_directions() {
+        let fov = [PI, PI];
+        for &mapping in &[
+            FisheyeMapping::Equidistant,
+            FisheyeMapping::Equisolid,
+            FisheyeMapping::Stereographic,
+        ] {
+            let direction = |point| angular_direction(Projection::Fisheye, mapping, fov, point);
+            assert!(close(
+                direction([PI / 2.0, PI / 2.0]).unwrap(),
+                [1.0, 0.0, 0.0]
+            ));
+            // the edges of the image are at 90 degrees from the gaze, the corners outside
+            assert!(close(direction([PI / 2.0, PI]).unwrap(), [0.0, 0.0, 1.0]));
+            assert!(close(direction([0.0, PI / 2.0]).unwrap(), [0.0, 1.0, 0.0]));
+            assert!(direction([0.0, 0.0]).is_none());
+            // the mapping and its inverse agree
+            for &angle in &[0.1, 1.0, 2.5] {
+                let radius = mapping.radius(angle);
+                assert!((mapping.angle(radius).unwrap() - angle).abs() < TOLERANCE);
+            }
+        }
+        assert!(FisheyeMapping::Equisolid.angle(2.1).is_none());
+    }
+
+    /// Points at equal distances from the center of a non-square image must be at equal angles
+    /// from the gaze, whichever the direction.
+    #[test]
+    fn fisheye_same_scale_along_both_sides() {
+        let fov = [2.0 * PI / 3.0, PI];
+        let angle = |point| {
+            let direction =
+                angular_direction(Projection::Fisheye, FisheyeMapping::Equisolid, fov, point);
+            direction.unwrap()[0].acos()
+        };
+        let center = [fov[0] / 2.0, fov[1] / 2.0];
+        for &distance in &[0.2, 0.6, fov[0] / 2.0] {
+            let vertical = angle([center[0] - distance, center[1]]);
+            let horizontal = angle([center[0], center[1] + distance]);
+            assert!((vertical - horizontal).abs() < TOLERANCE);
+        }
+        // the edges of the longer side are at its half field of view
+        assert!((angle([center[0], fov[1]]) - PI / 2.0).abs() < TOLERANCE);
+    }
+}
@@ -0,0 +1,99 @@
+/// Independent uses of random numbers. Each one gets its own sequence for every pixel and sample,
+/// so that enabling a feature does not change the numbers drawn by the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    /// Positions of the samples inside the pixels.
+    Pixel,
+    /// Points of the lens aperture that rays leave from.
+    Lens,
+    /// Moments within the shutter interval at which rays travel.
+    Time,
+    /// Scattering events of rays in media, and the light sources sampled at them.
+    Scatter,
+}
+
+/// SplitMix64 random number generator. Generators are derived from the scene seed and the pixel
+/// and sample they are used for, never shared between threads, so that images are the same
+/// whatever the number of threads and the order in which tiles are traced.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl Rng {
+    /// Generator for a whole pixel, the same for all its samples and passes.
+    pub fn for_pixel(seed: u64, stream: Stream, pixel: usize) -> Self {
+        Self {
+            state: hash(&[seed, stream as u64, pixel as u64]),
+        }
+    }
+
+    /// Generator for one sample of a pixel, in a given pass of a progressive render.
+    pub fn for_sample(seed: u64, stream: Stream, pixel: usize, pass: usize, sample: usize) -> Self {
+        Self {
+            state: hash(&[
+                seed,
+                stream as u64,
+                pixel as u64,
+                pass as u64,
+                sample as u64,
+            ]),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    /// Uniform number in [0, 1), from the 53 high bits of the next integer.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Hash of a few integers, mixing each of them in with the SplitMix64 finalizer.
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(GOLDEN_GAMMA, |hash, value| {
+        mix((hash ^ value).wrapping_add(GOLDEN_GAMMA))
+    })
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn independent_sequences() {
+        let draw = |mut rng: Rng| (0..4).map(|_| rng.next_u64()).collect::<Vec<_>>();
+        let sample = |seed, pixel, pass, sample| {
+            draw(Rng::for_sample(seed, Stream::Pixel, pixel, pass, sample))
+        };
+        assert_eq!(sample(1, 2, 3, 4), sample(1, 2, 3, 4));
+        assert_ne!(sample(1, 2, 3, 4), sample(0, 2, 3, 4));
+        assert_ne!(sample(1, 2, 3, 4), sample(1, 3, 3, 4));
+        assert_ne!(sample(1, 2, 3, 4), sample(1, 2, 4, 4));
+        assert_ne!(sample(1, 2, 3, 4), sample(1, 2, 3, 5));
+        assert_ne!(
+            draw(Rng::for_pixel(1, Stream::Pixel, 2)),
+            draw(Rng::for_pixel(1, Stream::Pixel, 3))
+        );
+    }
+
+    #[test]
+    fn uniform_numbers() {
+        let mut rng = Rng::for_pixel(0, Stream::Pixel, 0);
+        let numbers: Vec<f64> = (0..10_000).map(|_| rng.next_f64()).collect();
+        assert!(numbers.iter().all(|x| (0.0..1.0).contains(x)));
+        let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+        assert!((mean - 0.5).abs() < 0.01);
+    }
+}
@@ -1,54 +1,110 @@
 use {
-    crate::{Surface, SOP, VOP},
+    crate::{
+        medium::MediumStack, scattering::PathState, surface::Hit, Surface, SOP, SURFACE_OFFSET, VOP,
+    },
     nalgebra::{Point3, Unit, Vector3},
+    serde::Deserialize,
     std::sync::Arc,
 };
 
+/// Color given to rays that bounce more than the maximum number of times.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitResult {
+    #[default]
+    Black,
+    /// White light as absorbed by the media the ray has gone through.
+    Medium,
+    /// Fixed color, to spot where the limit is hit.
+    Debug(u8, u8, u8),
+}
+
+/// Maximum number of bounces of a ray, so that rays trapped between facing mirrors or by total
+/// internal reflection do not bounce forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BounceLimit {
+    pub max: usize,
+    pub result: LimitResult,
+}
+
+impl Default for BounceLimit {
+    fn default() -> Self {
+        Self {
+            max: 1000,
+            result: LimitResult::Black,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Ray {
     pub origin: Point3<f64>,
     pub direction: Vector3<f64>,
     pub vop: Arc<VOP>,
     pub abs: [f64; 3], // TODO: ray absorption when ray has no more intersections?
+    /// Moment within the camera shutter interval at which the ray travels, for moving surfaces.
+    pub time: f64,
+    /// Media the ray has entered, which decide the medium it goes into at every surface.
+    pub media: MediumStack,
+    /// State of the ray's path through scattering media.
+    pub path: PathState,
 }
 
 impl Ray {
-    // TODO: avoid repetition by calculating first_intersection twice
     /// Launch a ray through the system and fetch its final return value.
-    pub fn launch(&mut self, surfaces: &[Arc<dyn Surface + Send + Sync>]) -> BounceResult {
-        loop {
-            // get all first intersections with surfaces and distances to them
-            let intersections: Vec<Option<(Point3<f64>, f64)>> = surfaces
-                .iter()
-                .map(|s| s.intersection(self))
-                .map(|p| {
-                    if let Some(point) = p {
-                        Some((point, (point - self.origin).norm_squared()))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+    pub fn launch(
+        &mut self,
+        surfaces: &[Arc<dyn Surface + Send + Sync>],
+        limit: &BounceLimit,
+    ) -> BounceResult {
+        self.launch_after(surfaces, limit, 0)
+    }
 
-            // if no more intersections, return as Dark
-            if intersections.iter().all(|x| x.is_none()) {
-                return BounceResult::Kill;
+    /// Continue launching a ray that has already bounced the given number of times.
+    pub fn launch_after(
+        &mut self,
+        surfaces: &[Arc<dyn Surface + Send + Sync>],
+        limit: &BounceLimit,
+        mut bounces: usize,
+    ) -> BounceResult {
+        loop {
+            if bounces >= limit.max {
+                return self.limit_result(limit);
             }
 
-            // pick closest shape
-            let mut closest = f64::INFINITY;
-            let mut index = 0;
-            for (i, opt) in intersections.iter().enumerate() {
-                if opt.is_some() && opt.unwrap().1 <= closest {
-                    closest = opt.unwrap().1;
-                    index = i;
+            let closest = closest_hit(surfaces, self);
+
+            // the ray may scatter in the medium before reaching the surface
+            if self.vop.scatters() {
+                let distance = closest
+                    .as_ref()
+                    .map_or(f64::INFINITY, |(_, hit)| hit.t * self.direction.norm());
+                if self.scatter(surfaces, distance) {
+                    bounces += 1;
+                    continue;
                 }
             }
 
-            // bounce ray off closest shape
-            match self.bounce_unchecked(surfaces[index].as_ref(), &intersections[index].unwrap().0)
+            // if no more intersections, return as Dark
+            let (index, hit) = match closest {
+                Some(closest) => closest,
+                None => return self.dark(),
+            };
+
+            // light sources sampled at the last scattering event are already counted
+            if self.path.lights_sampled
+                && matches!(hit.sop, SOP::Light(..))
+                && surfaces[index]
+                    .sample_light(&self.origin, self.time, [0.5; 2])
+                    .is_some()
             {
-                BounceResult::Continue => continue,
+                return self.dark();
+            }
+
+            // bounce ray off closest shape
+            match self.bounce(&hit) {
+                BounceResult::Continue => bounces += 1,
+                BounceResult::Kill => return self.dark(),
                 BounceResult::Error => panic!("Something went wrong!"),
                 br => return br,
             }
@@ -56,83 +112,189 @@ impl Ray {
     }
 
     /// Analyze a ray incoming on a surface and determine the normal on the side of the incoming ray.
-    /// If no errors are found, return intersection point, that normal and the above & below VOPs.
-    /// Otherwise return an error.
-    #[allow(clippy::type_complexity)]
-    fn get_interaction_parameters_unchecked(
-        &self,
-        surface: &dyn Surface,
-        point: &Point3<f64>,
-    ) -> (Unit<Vector3<f64>>, Arc<VOP>, Arc<VOP>) {
-        // get VOPs above and below
-        let vop_above = surface.unchecked_vop_above_at(&point);
-        let vop_below = surface.unchecked_vop_below_at(&point);
-
-        // get normal at point
-        let normal = surface.unchecked_normal_at(&point);
-
+    /// Return that normal and the VOPs given for the side the ray is coming from and the side it
+    /// is going into.
+    fn get_interaction_parameters(&self, hit: &Hit) -> (Unit<Vector3<f64>>, Arc<VOP>, Arc<VOP>) {
         // ray is inbound from medium into which normal points
-        if normal.dot(&self.direction) <= 0.0 {
-            // check that ray VOP and above VOP match
-            if self.vop != vop_above {
-                panic!(
-                    "VOP mismatch:\nray: {:#?}\nfrom: {:?}\ninto: {:?}\nintersection: {:?}\nnormal: {:?}",
-                    self,
-                    vop_above,
-                    vop_below,
-                    point,
-                    normal,
-                )
-            }
-            (normal, vop_above, vop_below)
+        if hit.front_face {
+            (hit.normal, hit.vop_above.clone(), hit.vop_below.clone())
         // ray is inbound from other side of boundary
         } else {
-            if self.vop != vop_below {
-                panic!(
-                    "VOP mismatch:\nray: {:#?}\nfrom: {:?}\ninto: {:?}\nintersection: {:?}\nnormal: {:?}",
-                    self,
-                    vop_below,
-                    vop_above,
-                    point,
-                    normal,
-                )
+            (-hit.normal, hit.vop_below.clone(), hit.vop_above.clone())
+        }
+    }
+
+    /// Count a surface met from a medium other than the one given for that side, unless the media
+    /// the ray has entered account for it. The ray carries on from the medium it is in.
+    fn check_medium(&mut self, vop_from: &Arc<VOP>) {
+        if self.media.is_empty() && self.vop != *vop_from {
+            self.media.mismatches += 1;
+        }
+    }
+
+    /// Move the ray through its scattering medium towards a surface at the given distance, or
+    /// without one at an infinite distance. Return whether it scattered on the way, in which case
+    /// it leaves the scattering point in a direction drawn from the phase function, after
+    /// gathering the light of the light sources in view.
+    ///
+    /// The distance to the scattering point is drawn with the mean scattering coefficient of the
+    /// three channels, the absorption of every channel making up for the difference.
+    fn scatter(&mut self, surfaces: &[Arc<dyn Surface + Send + Sync>], distance: f64) -> bool {
+        let vop = self.vop.clone();
+        let mean = vop.scattering.iter().sum::<f64>() / 3.0;
+        let free_flight = -(1.0 - self.path.rng.next_f64()).ln() / mean;
+        if free_flight >= distance {
+            for c in 0..3 {
+                self.abs[c] += (vop.scattering[c] - mean) * distance;
+            }
+            return false;
+        }
+
+        let direction = self.direction.normalize();
+        let point = self.origin + free_flight * direction;
+        for c in 0..3 {
+            self.abs[c] += (vop.abs[c] + vop.scattering[c] - mean) * free_flight
+                - (vop.scattering[c] / mean).ln();
+        }
+
+        // single scattering towards every light source that can be sampled
+        for (index, surface) in surfaces.iter().enumerate() {
+            let u = [self.path.rng.next_f64(), self.path.rng.next_f64()];
+            let (towards, density) = match surface.sample_light(&point, self.time, u) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let shadow = Ray {
+                origin: point,
+                direction: towards,
+                vop: vop.clone(),
+                abs: [0.0; 3],
+                time: self.time,
+                media: self.media.clone(),
+                path: Default::default(),
+            };
+            if let Some((light, extinction)) = shadow.light_reached(surfaces, index) {
+                let phase = vop.phase.value(direction.dot(&towards.normalize()));
+                for (c, value) in light.iter().enumerate() {
+                    self.path.radiance[c] +=
+                        *value as f64 * phase / density * (-self.abs[c] - extinction[c]).exp();
+                }
+            }
+        }
+
+        let u = [self.path.rng.next_f64(), self.path.rng.next_f64()];
+        self.direction = vop.phase.sample(&direction, u);
+        self.origin = point;
+        self.path.lights_sampled = true;
+        true
+    }
+
+    /// Color of the light source of the given index, if this shadow ray reaches it, along with the
+    /// extinction of every channel on the way. Refracting surfaces between media of the same index
+    /// of refraction do not bend the ray, so it goes through them; any other surface blocks it.
+    fn light_reached(
+        mut self,
+        surfaces: &[Arc<dyn Surface + Send + Sync>],
+        index: usize,
+    ) -> Option<([u8; 3], [f64; 3])> {
+        let mut extinction = [0.0; 3];
+        loop {
+            let (k, hit) = closest_hit(surfaces, &self)?;
+            let length = hit.t * self.direction.norm();
+            for (c, value) in extinction.iter_mut().enumerate() {
+                *value += (self.vop.abs[c] + self.vop.scattering[c]) * length;
             }
-            (
-                Unit::new_normalize(-1.0 * normal.into_inner()),
-                vop_below,
-                vop_above,
-            )
+            match hit.sop {
+                SOP::Light(r, g, b) if k == index => return Some(([r, g, b], extinction)),
+                SOP::Refract => {
+                    let (_, vop_from, vop_into) = self.get_interaction_parameters(&hit);
+                    let bends = self
+                        .media
+                        .crossing(&vop_from, &vop_into, hit.priority)
+                        .is_some_and(|vop| vop.ior != self.vop.ior);
+                    if bends {
+                        return None;
+                    }
+                    self.bounce(&hit);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Result of a ray that reaches no light, with the light it gathered while scattering.
+    fn dark(&mut self) -> BounceResult {
+        match self.color([0.0; 3]) {
+            [0, 0, 0] => BounceResult::Kill,
+            [r, g, b] => BounceResult::Count(r, g, b),
         }
     }
 
-    pub fn bounce_unchecked(&mut self, surface: &dyn Surface, point: &Point3<f64>) -> BounceResult {
+    /// Color of the ray, from the light it reached and the light it gathered while scattering.
+    /// Gathered light comes in small fractions of a color unit, so it is dithered rather than
+    /// rounded down, keeping the mean of many rays right.
+    fn color(&mut self, reached: [f64; 3]) -> [u8; 3] {
+        let dither = if self.path.radiance == [0.0; 3] {
+            0.0
+        } else {
+            self.path.rng.next_f64()
+        };
+        let mut color = [0; 3];
+        for c in 0..3 {
+            color[c] = (reached[c] + self.path.radiance[c] + dither) as u8;
+        }
+        color
+    }
+
+    /// Result of a ray stopped by the bounce limit, with the light it gathered while scattering
+    /// unless it is given a fixed color.
+    fn limit_result(&mut self, limit: &BounceLimit) -> BounceResult {
+        let reached = match limit.result {
+            LimitResult::Black => [0.0; 3],
+            LimitResult::Medium => self.abs.map(|absorption| 255.0 * (-absorption).exp()),
+            LimitResult::Debug(r, g, b) => return BounceResult::Limit(r, g, b),
+        };
+        let [r, g, b] = self.color(reached);
+        BounceResult::Limit(r, g, b)
+    }
+
+    /// Bounce the ray off a surface it has hit.
+    pub fn bounce(&mut self, hit: &Hit) -> BounceResult {
         // update ray's own absorption factor by the distance traveled in the current VOP
-        let distance = (self.origin - *point).norm();
+        let distance = hit.t * self.direction.norm();
         for i in 0..=2 {
             self.abs[i] += self.vop.abs[i] * distance;
         }
 
-        let sop = surface.unchecked_sop_at(point);
-        match sop {
+        match hit.sop {
             SOP::Reflect => {
-                let (normal, _, _) = self.get_interaction_parameters_unchecked(surface, point);
-                self.reflect(point, &normal);
+                let (normal, vop_from, _) = self.get_interaction_parameters(hit);
+                self.check_medium(&vop_from);
+                self.reflect(&hit.point, &normal);
                 BounceResult::Continue
             }
             SOP::Refract => {
-                let (normal, vop_above, vop_below) =
-                    self.get_interaction_parameters_unchecked(surface, point);
-                self.refract(point, &normal, vop_above, vop_below);
+                let (normal, vop_from, vop_into) = self.get_interaction_parameters(hit);
+                match self.media.crossing(&vop_from, &vop_into, hit.priority) {
+                    Some(vop) => {
+                        self.check_medium(&vop_from);
+                        if !self.refract(&hit.point, &normal, vop) {
+                            return BounceResult::Continue;
+                        }
+                    }
+                    // the surface bounds a medium overridden here, so the ray goes straight on
+                    None => self.origin = offset_origin(&hit.point, &-normal),
+                }
+                self.media.cross(&vop_from, &vop_into, hit.priority);
                 BounceResult::Continue
             }
             SOP::Light(r, g, b) => {
-                let actual_rgb: Vec<u8> = self
-                    .abs
-                    .iter()
-                    .zip([r, g, b].iter())
-                    .map(|(absorption, value)| (*value as f64 * (-absorption).exp()) as u8)
-                    .collect();
-                BounceResult::Count(actual_rgb[0], actual_rgb[1], actual_rgb[2])
+                let mut reached = [0.0; 3];
+                for (c, value) in [r, g, b].iter().enumerate() {
+                    reached[c] = *value as f64 * (-self.abs[c]).exp();
+                }
+                let [r, g, b] = self.color(reached);
+                BounceResult::Count(r, g, b)
             }
             SOP::Dark => BounceResult::Kill,
         }
@@ -140,21 +302,22 @@ impl Ray {
 
     /// Reflect a ray in a surface.
     fn reflect(&mut self, intersection: &Point3<f64>, normal: &Vector3<f64>) {
-        self.origin = *intersection;
-        self.direction += 2.0 * self.direction.dot(&normal).abs() / normal.norm_squared() * *normal;
+        self.path.lights_sampled = false;
+        self.origin = offset_origin(intersection, normal);
+        self.direction += 2.0 * self.direction.dot(normal).abs() / normal.norm_squared() * *normal;
     }
 
-    /// Refract a ray in a surface.
+    /// Refract a ray in a surface, from its current VOP into the given one. Return whether the ray
+    /// went through, rather than being totally internally reflected.
     /// Reference: https://graphics.stanford.edu/courses/cs148-10-summer/docs/2006--degreve--reflection_refraction.pdf
     fn refract(
         &mut self,
         intersection: &Point3<f64>,
         normal: &Vector3<f64>,
-        vop_above: Arc<VOP>,
-        vop_below: Arc<VOP>,
-    ) {
+        vop_into: Arc<VOP>,
+    ) -> bool {
         // ratio of n_above / n_below
-        let nanb = vop_above.ior / vop_below.ior;
+        let nanb = self.vop.ior / vop_into.ior;
 
         // normal to surface at new contact point
         let normal = normal.normalize();
@@ -164,33 +327,66 @@ impl Ray {
 
         // critical angle
         if sin_sq_theta_t >= 1.0 {
-            return self.reflect(intersection, &normal);
+            self.reflect(intersection, &normal);
+            return false;
         }
 
-        // update ray origin to point of intersection
-        self.origin = *intersection;
+        // light sources sampled through the surface are still ahead unless it bends the ray
+        if vop_into.ior != self.vop.ior {
+            self.path.lights_sampled = false;
+        }
+        // update ray origin to point of intersection, on the far side of the surface
+        self.origin = offset_origin(intersection, &-normal);
         // update ray direction
         self.direction =
             self.direction * nanb + normal * (nanb * cos_theta_i - (1.0 - sin_sq_theta_t).sqrt());
 
         // update ray VOP
-        self.vop = vop_below;
+        self.vop = vop_into;
+        true
+    }
+}
+
+/// Closest hit of a ray out of the first intersections with all surfaces, along with the index of
+/// the surface hit.
+fn closest_hit<'a>(
+    surfaces: &'a [Arc<dyn Surface + Send + Sync>],
+    ray: &Ray,
+) -> Option<(usize, Hit<'a>)> {
+    let mut closest: Option<(usize, Hit)> = None;
+    for (index, surface) in surfaces.iter().enumerate() {
+        if let Some(hit) = surface.intersection(ray) {
+            if closest.as_ref().is_none_or(|(_, c)| hit.t <= c.t) {
+                closest = Some((index, hit));
+            }
+        }
     }
+    closest
+}
+
+/// Start of a ray leaving a surface at the given point, moved off the surface towards the side
+/// the normal points to by a distance scaled with the size of the coordinates. Shapes can then
+/// accept every hit ahead of the origin without ever finding the point the ray left from.
+fn offset_origin(intersection: &Point3<f64>, normal: &Vector3<f64>) -> Point3<f64> {
+    let offset = SURFACE_OFFSET * (1.0 + intersection.coords.amax());
+    intersection + offset * normal.normalize()
 }
 
 /// Result returned by ray bounce operation. This can be one of the following:
 /// * `Count` - the ray has reached a light source and therefore must be counted.
 /// * `Kill` - the ray has reached a determined "dark" spot (either due to being out-of bounds or
-///     a perfectly absorbant material) and is to be gracefully terminated.
+///   a perfectly absorbant material) and is to be gracefully terminated.
 /// * `Continue` - the ray has interacted normally and can continue along its merry way.
-/// * `Error` - the ray has encountered an error (for example a ray with VOP of RI=1.0 has been
-///     registered as hitting a surface at VOP with RI=1.5), with custom implementation of what
-///     happens in this case.
+/// * `Limit` - the ray has bounced the maximum number of times and is given the color set for
+///   this case.
+/// * `Error` - the ray has encountered an error, with custom implementation of what happens in
+///   this case.
 #[derive(Debug, PartialEq)]
 pub enum BounceResult {
     Count(u8, u8, u8),
     Kill,
     Continue,
+    Limit(u8, u8, u8),
     Error,
 }
 
@@ -199,23 +395,20 @@ mod tests {
     use {
         super::*,
         crate::{
-            surface::plane::{simple::Plane, PlaneShape},
+            surface::{
+                plane::{simple::Plane, PlaneShape},
+                sphere::{simple::Sphere, SphereShape},
+            },
             TOLERANCE,
         },
     };
 
     fn air() -> Arc<VOP> {
-        Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0, 0.0, 0.0],
-        })
+        Arc::new(VOP::new(1.0, [0.0, 0.0, 0.0]))
     }
 
     fn glass() -> Arc<VOP> {
-        Arc::new(VOP {
-            ior: 1.5,
-            abs: [0.0, 0.0, 0.0],
-        })
+        Arc::new(VOP::new(1.5, [0.0, 0.0, 0.0]))
     }
 
     fn reflective_plane(air: Arc<VOP>) -> Plane {
@@ -250,14 +443,20 @@ mod tests {
             let glass = glass();
             let plane = refractive_plane(air.clone(), glass);
             let mut downward_ray = Ray {
-                origin: Point3::origin(),
+                origin: Point3::new(0.0, 0.0, 1.0),
                 direction: -Vector3::z(),
                 vop: air,
                 abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
-            downward_ray.bounce_unchecked(&plane, &Point3::origin());
+            let hit = plane.intersection(&downward_ray).unwrap();
+            downward_ray.bounce(&hit);
             assert_eq!(downward_ray.direction.normalize(), -Vector3::z());
-            assert_eq!(downward_ray.origin, Point3::origin());
+            // the new origin is just off the plane, on the side the ray goes into
+            assert!(downward_ray.origin.z < 0.0);
+            assert!((downward_ray.origin - Point3::origin()).norm() <= TOLERANCE);
         }
 
         #[test]
@@ -270,22 +469,25 @@ mod tests {
                 direction: Vector3::new(-1.0, 0.0, -1.0),
                 vop: air,
                 abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
             let mut ray = original_ray.clone();
-            let intersection = plane.intersection(&original_ray).unwrap();
-            ray.bounce_unchecked(&plane, &Point3::origin());
+            let hit = plane.intersection(&original_ray).unwrap();
+            ray.bounce(&hit);
 
             // calculate via snell's law
-            let normal = plane.unchecked_normal_at(&ray.origin);
+            let normal = hit.normal;
             let theta_i = normal
                 .dot(&(-1.0 * original_ray.direction).normalize())
                 .acos();
             let theta_t = (-1.0 * normal.into_inner())
                 .dot(&ray.direction.normalize())
                 .acos();
+            assert!(hit.front_face);
             assert!(
-                plane.unchecked_vop_above_at(&intersection).ior * theta_i.sin()
-                    - plane.unchecked_vop_below_at(&intersection).ior * theta_t.sin()
+                hit.vop_above.ior * theta_i.sin() - hit.vop_below.ior * theta_t.sin()
                     <= f64::EPSILON
             );
         }
@@ -300,18 +502,21 @@ mod tests {
                 direction: Vector3::new(-0.2, 0.0, 1.0),
                 vop: glass,
                 abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
-            let intersection = plane.intersection(&original_ray).unwrap();
+            let hit = plane.intersection(&original_ray).unwrap();
             let mut ray = original_ray.clone();
-            ray.bounce_unchecked(&plane, &Point3::origin());
+            ray.bounce(&hit);
 
             // calculate via snell's law
-            let normal = plane.unchecked_normal_at(&ray.origin);
+            let normal = hit.normal;
             let theta_i = normal.dot(&(original_ray.direction).normalize()).acos();
             let theta_t = normal.dot(&ray.direction.normalize());
+            assert!(!hit.front_face);
             assert!(
-                plane.unchecked_vop_below_at(&intersection).ior * theta_i.sin()
-                    - plane.unchecked_vop_above_at(&intersection).ior * theta_t.sin()
+                hit.vop_below.ior * theta_i.sin() - hit.vop_above.ior * theta_t.sin()
                     <= f64::EPSILON
             );
         }
@@ -325,9 +530,14 @@ mod tests {
                 direction: Vector3::new(-1.0, 0.0, -1.0),
                 vop: air,
                 abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
-            ray.bounce_unchecked(&sphere, &Point3::origin());
-            assert_eq!(ray.origin, Point3::origin());
+            let hit = sphere.intersection(&ray).unwrap();
+            ray.bounce(&hit);
+            assert!(ray.origin.z > 0.0);
+            assert!((ray.origin - Point3::origin()).norm() <= TOLERANCE);
             assert!(
                 (ray.direction.normalize() - Vector3::new(-1.0, 0.0, 1.0).normalize())
                     .norm_squared()
@@ -335,7 +545,36 @@ mod tests {
             );
         }
 
-        // TODO: test TIR
+        /// A ray trapped in a glass sphere by total internal reflection, hitting it at a grazing
+        /// angle every time, must go round the sphere until the bounce limit, however short the
+        /// chords between its bounces.
+        #[test]
+        fn grazing_total_internal_reflection() {
+            let glass = glass();
+            let sphere: Arc<dyn Surface + Send + Sync> = Arc::new(Sphere {
+                geometry: SphereShape::new(Point3::origin(), 1.0, None, None),
+                sop: SOP::Refract,
+                vop_above: air(),
+                vop_below: glass.clone(),
+            });
+            // chords of length 1e-3 between bounces
+            let mut ray = Ray {
+                origin: Point3::new(0.0, (1.0 - 2.5e-7f64).sqrt(), 0.0),
+                direction: Vector3::x(),
+                vop: glass,
+                abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
+            };
+            let limit = BounceLimit {
+                max: 100,
+                result: LimitResult::Black,
+            };
+            assert_eq!(ray.launch(&[sphere], &limit), BounceResult::Limit(0, 0, 0));
+            let radius = ray.origin.coords.norm();
+            assert!(radius < 1.0 && radius > 1.0 - TOLERANCE);
+        }
     }
 
     #[cfg(test)]
@@ -350,6 +589,9 @@ mod tests {
                 direction: Vector3::new(0.0, 0.0, -1.0),
                 vop,
                 abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             }
         }
 
@@ -377,7 +619,7 @@ mod tests {
             let mut ray = downwards_ray(air.clone());
             let plane = light_plane(air);
             assert_eq!(
-                ray.launch(&[Arc::new(plane)]),
+                ray.launch(&[Arc::new(plane)], &BounceLimit::default()),
                 BounceResult::Count(255, 255, 255)
             );
         }
@@ -387,7 +629,56 @@ mod tests {
             let air = air();
             let mut ray = downwards_ray(air.clone());
             let plane = dark_plane(air);
-            assert_eq!(ray.launch(&[Arc::new(plane)]), BounceResult::Kill);
+            assert_eq!(
+                ray.launch(&[Arc::new(plane)], &BounceLimit::default()),
+                BounceResult::Kill
+            );
+        }
+
+        /// Rays trapped between facing mirrors must stop at the bounce limit with its color.
+        #[test]
+        fn ray_stopped_at_bounce_limit() {
+            let dark_air = Arc::new(VOP::new(1.0, [0.01, 0.0, 0.0]));
+            let mut top = reflective_plane(dark_air.clone());
+            top.geometry = PlaneShape::new(Point3::new(0.0, 0.0, 20.0), Vector3::z(), None);
+            let mirrors: Vec<Arc<dyn Surface + Send + Sync>> =
+                vec![Arc::new(reflective_plane(dark_air.clone())), Arc::new(top)];
+
+            let debug = BounceLimit {
+                max: 5,
+                result: LimitResult::Debug(255, 0, 255),
+            };
+            let mut ray = downwards_ray(dark_air.clone());
+            assert_eq!(
+                ray.launch(&mirrors, &debug),
+                BounceResult::Limit(255, 0, 255)
+            );
+
+            // 10 units down, then 20 units for each of the 4 bounces after the first
+            let medium = BounceLimit {
+                max: 5,
+                result: LimitResult::Medium,
+            };
+            let mut ray = downwards_ray(dark_air.clone());
+            let red = (255.0 * (-0.9_f64).exp()) as u8;
+            assert_eq!(
+                ray.launch(&mirrors, &medium),
+                BounceResult::Limit(red, 255, 255)
+            );
+
+            // light gathered while scattering is kept
+            let mut ray = downwards_ray(dark_air);
+            ray.path.radiance = [10.0, 0.0, 0.0];
+            assert_eq!(
+                ray.launch(
+                    &mirrors,
+                    &BounceLimit {
+                        max: 5,
+                        ..Default::default()
+                    }
+                ),
+                BounceResult::Limit(10, 0, 0)
+            );
         }
 
         #[test]
@@ -395,7 +686,62 @@ mod tests {
             let air = air();
             let mut ray = downwards_ray(air.clone());
             let plane = reflective_plane(air);
-            assert_eq!(ray.launch(&[Arc::new(plane)]), BounceResult::Kill);
+            assert_eq!(
+                ray.launch(&[Arc::new(plane)], &BounceLimit::default()),
+                BounceResult::Kill
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod shadow {
+        //! Test that shadow rays from scattering events reach light sources through the surfaces
+        //! that do not bend them, and no others.
+        use super::*;
+
+        fn light_through(outside: Arc<VOP>) -> Option<([u8; 3], [f64; 3])> {
+            let fog = Arc::new(VOP {
+                scattering: [0.1, 0.2, 0.0],
+                ..VOP::new(1.0, [0.0; 3])
+            });
+            let surfaces: Vec<Arc<dyn Surface + Send + Sync>> = vec![
+                Arc::new(Sphere {
+                    geometry: SphereShape::new(Point3::origin(), 2.0, None, None),
+                    sop: SOP::Refract,
+                    vop_above: outside.clone(),
+                    vop_below: fog.clone(),
+                }),
+                Arc::new(Sphere {
+                    geometry: SphereShape::new(Point3::new(0.0, 5.0, 0.0), 1.0, None, None),
+                    sop: SOP::Light(200, 100, 0),
+                    vop_above: outside.clone(),
+                    vop_below: outside,
+                }),
+            ];
+            let shadow = Ray {
+                origin: Point3::origin(),
+                direction: Vector3::y(),
+                vop: fog,
+                abs: [0.0; 3],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
+            };
+            shadow.light_reached(&surfaces, 1)
+        }
+
+        #[test]
+        fn through_clear_container() {
+            let (light, extinction) = light_through(air()).unwrap();
+            assert_eq!(light, [200, 100, 0]);
+            for (c, expected) in [0.2, 0.4, 0.0].iter().enumerate() {
+                assert!((extinction[c] - expected).abs() < TOLERANCE);
+            }
+        }
+
+        #[test]
+        fn blocked_by_refraction() {
+            assert!(light_through(glass()).is_none());
         }
     }
 }
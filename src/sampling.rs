@@ -0,0 +1,330 @@
+use {
+    crate::{
+        progressive::pass_offset,
+        random::{Rng, Stream},
+    },
+    serde::Deserialize,
+};
+
+/// Placement of the samples inside a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// One sample per subpixel, at the same position in all of them.
+    #[default]
+    Grid,
+    /// Uniformly random samples over the whole pixel.
+    Random,
+    /// One uniformly random sample per subpixel.
+    Jittered,
+    /// Halton sequence in bases 2 and 3, randomly shifted for every pixel.
+    Halton,
+    /// Sobol sequence, randomly scrambled for every pixel.
+    Sobol,
+}
+
+/// Sample pattern, along with the seed its random numbers are derived from, if not the seed of
+/// the scene.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Sampling {
+    #[serde(default)]
+    pub pattern: Pattern,
+    pub seed: Option<u64>,
+}
+
+/// Sample of a pixel split into n x n subpixels: the row and column of the subpixel it falls in
+/// and its position inside that subpixel, as fractions of the subpixel size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub cell: [usize; 2],
+    pub offset: [f64; 2],
+}
+
+impl Sample {
+    /// Sample at a position given as fractions of the pixel size.
+    fn at(position: [f64; 2], n: usize) -> Self {
+        let scaled = position.map(|p| p * n as f64);
+        let cell = scaled.map(|s| (s as usize).min(n - 1));
+        Self {
+            cell,
+            offset: [scaled[0] - cell[0] as f64, scaled[1] - cell[1] as f64],
+        }
+    }
+
+    /// Position inside the pixel, as fractions of the pixel size.
+    pub fn position(&self, n: usize) -> [f64; 2] {
+        [
+            (self.cell[0] as f64 + self.offset[0]) / n as f64,
+            (self.cell[1] as f64 + self.offset[1]) / n as f64,
+        ]
+    }
+}
+
+impl Sampling {
+    /// The n x n samples of a pixel, given by its index in the full frame, for a given pass of a
+    /// progressive render. Every pass gets new samples, and so do refinements of a pixel within a
+    /// pass, following the number of samples already `taken` in it.
+    pub fn pixel_samples(
+        &self,
+        seed: u64,
+        pixel: usize,
+        n: usize,
+        pass: usize,
+        taken: usize,
+    ) -> Vec<Sample> {
+        let seed = self.seed.unwrap_or(seed);
+        let random = |k: usize| {
+            let mut rng = Rng::for_sample(seed, Stream::Pixel, pixel, pass, taken + k);
+            [rng.next_f64(), rng.next_f64()]
+        };
+        let cells = (0..n).flat_map(|a| (0..n).map(move |b| [a, b]));
+        let first = (pass * n * n + taken) as u64;
+
+        match self.pattern {
+            Pattern::Grid => cells
+                .map(|cell| Sample {
+                    cell,
+                    offset: pass_offset(pass),
+                })
+                .collect(),
+            Pattern::Random => (0..n * n).map(|k| Sample::at(random(k), n)).collect(),
+            Pattern::Jittered => cells
+                .zip(0..)
+                .map(|(cell, k)| Sample {
+                    cell,
+                    offset: random(k),
+                })
+                .collect(),
+            Pattern::Halton => {
+                // the same shift for all the passes, so that they continue the same sequence
+                let mut rng = Rng::for_pixel(seed, Stream::Pixel, pixel);
+                let shift = [rng.next_f64(), rng.next_f64()];
+                (first..first + (n * n) as u64)
+                    .map(|index| {
+                        let point = [radical_inverse(index, 2), radical_inverse(index, 3)];
+                        Sample::at([0, 1].map(|d| (point[d] + shift[d]).fract()), n)
+                    })
+                    .collect()
+            }
+            Pattern::Sobol => {
+                let mut rng = Rng::for_pixel(seed, Stream::Pixel, pixel);
+                let scramble = [rng.next_u64() as u32, rng.next_u64() as u32];
+                (first..first + (n * n) as u64)
+                    .map(|index| {
+                        let point = sobol(index as u32);
+                        Sample::at(
+                            [0, 1].map(|d| (point[d] ^ scramble[d]) as f64 / 4_294_967_296.0),
+                            n,
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Digits of the index in the given base, mirrored around the decimal point.
+fn radical_inverse(mut index: u64, base: u64) -> f64 {
+    let mut result = 0.0;
+    let mut scale = 1.0 / base as f64;
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale /= base as f64;
+    }
+    result
+}
+
+/// First two dimensions of the Sobol sequence, as fractions of 2^32.
+fn sobol(index: u32) -> [u32; 2] {
+    let mut second = 0;
+    let mut direction: u32 = 1 << 31;
+    for bit in 0..32 {
+        if index >> bit & 1 == 1 {
+            second ^= direction;
+        }
+        direction ^= direction >> 1;
+    }
+    [index.reverse_bits(), second]
+}
+
+/// Reconstruction filter, weighting the samples around a pixel by their distance to its center.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Plain average of the samples of the pixel.
+    #[default]
+    Box,
+    /// Linear falloff, over a radius of 1 pixel.
+    Tent,
+    /// Gaussian of standard deviation 0.5 pixels, cut off at 1.5 pixels.
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3, over a radius of 2 pixels.
+    Mitchell,
+}
+
+impl Filter {
+    /// Radius of the filter, in pixels.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+        }
+    }
+
+    /// Number of neighbouring pixels on each side whose samples fall within the filter.
+    pub fn reach(&self) -> usize {
+        (self.radius() - 0.5).ceil() as usize
+    }
+
+    /// Weight of a sample at a given offset from the pixel center, in pixels.
+    pub fn weight(&self, offset: [f64; 2]) -> f64 {
+        self.weight_1d(offset[0]) * self.weight_1d(offset[1])
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => 1.0 - x,
+            Filter::Gaussian => (-2.0 * x * x).exp() - (-2.0 * 1.5 * 1.5_f64).exp(),
+            Filter::Mitchell => {
+                const B: f64 = 1.0 / 3.0;
+                const C: f64 = 1.0 / 3.0;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * B - 6.0 * C) * x.powi(3)
+                        + (-18.0 + 12.0 * B + 6.0 * C) * x.powi(2)
+                        + (6.0 - 2.0 * B))
+                        / 6.0
+                } else {
+                    ((-B - 6.0 * C) * x.powi(3)
+                        + (6.0 * B + 30.0 * C) * x.powi(2)
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampling(pattern: Pattern) -> Sampling {
+        Sampling {
+            pattern,
+            seed: Some(7),
+        }
+    }
+
+    /// Every pattern must give n x n samples inside the pixel, the same ones for the same seed.
+    #[test]
+    fn samples_inside_pixel() {
+        for &pattern in &[
+            Pattern::Grid,
+            Pattern::Random,
+            Pattern::Jittered,
+            Pattern::Halton,
+            Pattern::Sobol,
+        ] {
+            let samples = sampling(pattern).pixel_samples(0, 12, 3, 1, 0);
+            assert_eq!(samples.len(), 9);
+            for sample in &samples {
+                assert!(sample.cell[0] < 3 && sample.cell[1] < 3);
+                assert!(sample.offset.iter().all(|o| (0.0..1.0).contains(o)));
+            }
+            assert_eq!(samples, sampling(pattern).pixel_samples(0, 12, 3, 1, 0));
+            if pattern != Pattern::Grid {
+                assert_ne!(samples, sampling(pattern).pixel_samples(0, 13, 3, 1, 0));
+                let other_seed = Sampling {
+                    pattern,
+                    seed: Some(8),
+                };
+                assert_ne!(samples, other_seed.pixel_samples(0, 12, 3, 1, 0));
+                // without its own seed, the sampling uses the scene seed
+                let scene_seed = Sampling {
+                    pattern,
+                    seed: None,
+                };
+                assert_eq!(samples, scene_seed.pixel_samples(7, 12, 3, 1, 0));
+            }
+        }
+    }
+
+    /// Stratified patterns must put exactly one sample in each subpixel.
+    #[test]
+    fn stratification() {
+        for &pattern in &[Pattern::Jittered, Pattern::Sobol] {
+            let mut cells: Vec<[usize; 2]> = sampling(pattern)
+                .pixel_samples(0, 3, 4, 0, 0)
+                .iter()
+                .map(|s| s.cell)
+                .collect();
+            cells.sort_unstable();
+            cells.dedup();
+            assert_eq!(cells.len(), 16);
+        }
+    }
+
+    /// Refinements of a pixel must take new samples, none of them at the positions of the samples
+    /// already taken.
+    #[test]
+    fn refinements_take_new_samples() {
+        for &pattern in &[
+            Pattern::Grid,
+            Pattern::Random,
+            Pattern::Jittered,
+            Pattern::Halton,
+            Pattern::Sobol,
+        ] {
+            let base: Vec<[f64; 2]> = sampling(pattern)
+                .pixel_samples(0, 5, 2, 0, 0)
+                .iter()
+                .map(|s| s.position(2))
+                .collect();
+            for sample in sampling(pattern).pixel_samples(0, 5, 4, 0, 4) {
+                assert!(!base.contains(&sample.position(4)));
+            }
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_sequences() {
+        assert_eq!(
+            (0..4).map(|i| radical_inverse(i, 2)).collect::<Vec<_>>(),
+            vec![0.0, 0.5, 0.25, 0.75]
+        );
+        assert!((radical_inverse(5, 3) - 7.0 / 9.0).abs() < 1e-12);
+        // second Sobol dimension: 0, 1/2, 3/4, 1/4
+        assert_eq!(
+            (0..4).map(|i| sobol(i)[1] >> 30).collect::<Vec<_>>(),
+            vec![0, 2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn filter_weights() {
+        for &filter in &[
+            Filter::Box,
+            Filter::Tent,
+            Filter::Gaussian,
+            Filter::Mitchell,
+        ] {
+            assert!(filter.weight([0.0, 0.0]) > 0.0);
+            assert!(filter.weight([0.0, 0.0]) >= filter.weight([0.4, 0.3]));
+            assert_eq!(filter.weight([filter.radius() + 0.1, 0.0]), 0.0);
+        }
+        assert_eq!(Filter::Box.reach(), 0);
+        assert_eq!(Filter::Mitchell.reach(), 2);
+        assert!((Filter::Mitchell.weight_1d(0.0) - 8.0 / 9.0).abs() < 1e-12);
+        assert!(Filter::Mitchell.weight_1d(1.5) < 0.0);
+        assert!(Filter::Mitchell.weight_1d(2.0).abs() < 1e-12);
+    }
+}
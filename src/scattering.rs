@@ -0,0 +1,181 @@
+use {
+    crate::random::{Rng, Stream},
+    nalgebra::Vector3,
+    serde::Deserialize,
+    std::f64::consts::PI,
+};
+
+/// Distribution of the directions light scatters into within a medium, relative to the direction
+/// it travelled in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Same in all directions.
+    #[default]
+    Isotropic,
+    /// Henyey-Greenstein phase function with the given asymmetry, from -1 (backward) through 0
+    /// (isotropic) to 1 (forward), such as 0.7 to 0.9 for fog and smoke.
+    HenyeyGreenstein(f64),
+}
+
+impl Phase {
+    /// Density of scattering into a direction at an angle of the given cosine from the direction
+    /// of travel, per unit solid angle.
+    pub fn value(self, cos_angle: f64) -> f64 {
+        match self {
+            Phase::Isotropic => 1.0 / (4.0 * PI),
+            Phase::HenyeyGreenstein(g) => {
+                let denominator = 1.0 + g * g - 2.0 * g * cos_angle;
+                (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+            }
+        }
+    }
+
+    /// Direction scattered into from a given direction of travel, drawn from the phase function
+    /// with two uniform numbers.
+    pub fn sample(self, direction: &Vector3<f64>, u: [f64; 2]) -> Vector3<f64> {
+        let cos_angle = match self {
+            Phase::HenyeyGreenstein(g) if g.abs() > 1e-3 => {
+                let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u[0]);
+                ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+            }
+            _ => 1.0 - 2.0 * u[0],
+        };
+        let sin_angle = (1.0 - cos_angle * cos_angle).max(0.0).sqrt();
+        let phi = 2.0 * PI * u[1];
+        let (w, a, b) = basis(direction);
+        cos_angle * w + sin_angle * (phi.cos() * a + phi.sin() * b)
+    }
+}
+
+/// Unit vector along the given direction, along with two unit vectors orthogonal to it and to each
+/// other.
+pub fn basis(direction: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>) {
+    let w = direction.normalize();
+    let other = if w.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let a = other.cross(&w).normalize();
+    (w, a, w.cross(&a))
+}
+
+/// What a ray carries along its path through scattering media: its random numbers, the light
+/// gathered from light sources at every scattering event, and whether the light sources sampled at
+/// the last event are still the ones directly ahead of it.
+#[derive(Debug, Clone)]
+pub struct PathState {
+    pub rng: Rng,
+    pub radiance: [f64; 3],
+    pub lights_sampled: bool,
+}
+
+impl Default for PathState {
+    fn default() -> Self {
+        Self {
+            rng: Rng::for_pixel(0, Stream::Scatter, 0),
+            radiance: [0.0; 3],
+            lights_sampled: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            ray::BounceLimit,
+            surface::sphere::{simple::Sphere, SphereShape},
+            BounceResult, Ray, Surface, SOP, TOLERANCE, VOP,
+        },
+        nalgebra::Point3,
+        std::sync::Arc,
+    };
+
+    /// Phase functions must integrate to 1 over all directions, and their samples must follow
+    /// them, on average as far forward as the asymmetry.
+    #[test]
+    fn phase_functions() {
+        let steps = 2000;
+        for phase in [Phase::Isotropic, Phase::HenyeyGreenstein(0.8)] {
+            let integral: f64 = (0..steps)
+                .map(|k| -1.0 + (k as f64 + 0.5) * 2.0 / steps as f64)
+                .map(|cos_angle| phase.value(cos_angle) * 2.0 * PI * 2.0 / steps as f64)
+                .sum();
+            assert!((integral - 1.0).abs() < 1e-3);
+        }
+
+        let mut rng = Rng::for_pixel(0, Stream::Scatter, 0);
+        let n = 20000;
+        let mean_cos: f64 = (0..n)
+            .map(|_| {
+                let u = [rng.next_f64(), rng.next_f64()];
+                let direction = Phase::HenyeyGreenstein(0.6).sample(&Vector3::z(), u);
+                assert!((direction.norm() - 1.0).abs() < TOLERANCE);
+                direction.z
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!((mean_cos - 0.6).abs() < 0.02);
+    }
+
+    /// Rays looking away from a light through fog must gather its light by scattering, only in
+    /// the channels that scatter, and none without fog.
+    #[test]
+    fn fog_gathers_light() {
+        let fog = |scattering| {
+            Arc::new(VOP {
+                ior: 1.0,
+                abs: [0.0; 3],
+                scattering,
+                phase: Phase::HenyeyGreenstein(-0.3),
+            })
+        };
+        let mean_color = |vop: Arc<VOP>| {
+            let surfaces: Vec<Arc<dyn Surface + Send + Sync>> = vec![
+                Arc::new(Sphere {
+                    geometry: SphereShape::new(Point3::new(0.0, 5.0, 0.0), 1.0, None, None),
+                    sop: SOP::Light(200, 200, 200),
+                    vop_above: vop.clone(),
+                    vop_below: vop.clone(),
+                }),
+                Arc::new(Sphere {
+                    geometry: SphereShape::new(Point3::origin(), 20.0, None, None),
+                    sop: SOP::Dark,
+                    vop_above: vop.clone(),
+                    vop_below: vop.clone(),
+                }),
+            ];
+            let n = 400;
+            let mut sum = [0.0; 3];
+            for k in 0..n {
+                let mut ray = Ray {
+                    origin: Point3::origin(),
+                    direction: -Vector3::y(),
+                    vop: vop.clone(),
+                    abs: [0.0; 3],
+                    time: 0.0,
+                    media: Default::default(),
+                    path: PathState {
+                        rng: Rng::for_pixel(0, Stream::Scatter, k),
+                        ..Default::default()
+                    },
+                };
+                if let BounceResult::Count(r, g, b) = ray.launch(&surfaces, &BounceLimit::default())
+                {
+                    for (c, value) in [r, g, b].iter().enumerate() {
+                        sum[c] += *value as f64 / n as f64;
+                    }
+                }
+            }
+            sum
+        };
+        assert_eq!(mean_color(fog([0.0; 3])), [0.0; 3]);
+        let [r, g, b] = mean_color(fog([0.1, 0.1, 0.0]));
+        assert!(r > 1.0 && r < 200.0);
+        assert!(g > 1.0 && g < 200.0);
+        assert_eq!(b, 0.0);
+    }
+}
@@ -0,0 +1,204 @@
+use {
+    crate::{
+        animation::Animation,
+        camera::{Camera, CameraBuilder},
+        checkpoint::CheckpointSettings,
+        medium::Prioritized,
+        motion::{Motion, Moving},
+        progressive::Progressive,
+        ray::{BounceLimit, LimitResult},
+        surface::{
+            BezierBuilder, CheckerboardBuilder, CylinderBuilder, ImplicitBuilder,
+            MandelbrotPlaneBuilder, ParaboloidBuilder, PlaneBuilder, RectangleBuilder,
+            SphereBuilder, SurfaceBuilder, TexturedRectangleBuilder,
+        },
+        Surface, VOP,
+    },
+    serde_yaml::{from_value, Mapping, Value},
+    std::{collections::HashMap, sync::Arc},
+};
+
+/// Extract the path to the image to be saved.
+pub fn extract_filepath(lhm: &Mapping) -> String {
+    lhm.get(&Value::String("filepath".to_owned()))
+        .expect("No filepath given.")
+        .as_str()
+        .expect("Filepath must be a string.")
+        .to_owned()
+}
+
+/// Extract VOPs.
+pub fn extract_vops(lhm: &Mapping) -> HashMap<String, Arc<VOP>> {
+    let volumes = lhm
+        .get(&Value::String("volumes".to_owned()))
+        .expect("No volumes given.")
+        .as_mapping()
+        .expect("Volumes must be given as dictionary.");
+
+    volumes
+        .into_iter()
+        .map(|(k, v)| {
+            (
+                k.as_str()
+                    .expect("Volume names must be strings.")
+                    .to_owned(),
+                Arc::new(from_value(v.clone()).expect("Could not parse volume")),
+            )
+        })
+        .collect()
+}
+
+/// Get the camera configuration.
+pub fn extract_camera(lhm: &Mapping, vop_map: &HashMap<String, Arc<VOP>>) -> Camera {
+    let camera_builder: CameraBuilder = from_value(
+        lhm.get(&Value::String("camera".to_owned()))
+            .expect("No camera given.")
+            .to_owned(),
+    )
+    .expect("Could not parse camera.");
+    let mut camera = camera_builder.build(vop_map);
+    camera.seed = extract_seed(lhm);
+    camera.time = extract_time(lhm);
+    camera.bounce_limit = extract_bounce_limit(lhm);
+    camera
+}
+
+/// Get the maximum number of bounces of the rays and the color of those that reach it, by default
+/// 1000 bounces and black.
+pub fn extract_bounce_limit(lhm: &Mapping) -> BounceLimit {
+    let default = BounceLimit::default();
+    BounceLimit {
+        max: lhm
+            .get(&Value::String("max_bounces".to_owned()))
+            .map_or(default.max, |v| {
+                v.as_u64()
+                    .expect("Maximum number of bounces must be a non-negative integer.")
+                    as usize
+            }),
+        result: lhm
+            .get(&Value::String("max_bounces_result".to_owned()))
+            .map_or(default.result, |v| {
+                from_value::<LimitResult>(v.to_owned())
+                    .expect("Could not parse the result of rays reaching the bounce limit.")
+            }),
+    }
+}
+
+/// Get the seed all random numbers are derived from, 0 if not given.
+pub fn extract_seed(lhm: &Mapping) -> u64 {
+    lhm.get(&Value::String("seed".to_owned())).map_or(0, |v| {
+        v.as_u64().expect("Seed must be a non-negative integer.")
+    })
+}
+
+/// Get the time of the image in seconds, 0 if not given.
+pub fn extract_time(lhm: &Mapping) -> f64 {
+    lhm.get(&Value::String("time".to_owned()))
+        .map_or(0.0, |v| v.as_f64().expect("Time must be a number."))
+}
+
+/// Get the checkpoint settings, if any.
+pub fn extract_checkpoint(lhm: &Mapping) -> Option<CheckpointSettings> {
+    lhm.get(&Value::String("checkpoint".to_owned()))
+        .map(|v| from_value(v.to_owned()).expect("Could not parse checkpoint settings."))
+}
+
+/// Get the animation settings, if any.
+pub fn extract_animation(lhm: &Mapping) -> Option<Animation> {
+    lhm.get(&Value::String("animation".to_owned())).map(|v| {
+        let animation: Animation =
+            from_value(v.to_owned()).expect("Could not parse animation settings.");
+        animation.validate(lhm);
+        animation
+    })
+}
+
+/// Get the progressive rendering settings, if any.
+pub fn extract_progressive(lhm: &Mapping) -> Option<Progressive> {
+    lhm.get(&Value::String("progressive".to_owned()))
+        .map(|v| from_value(v.to_owned()).expect("Could not parse progressive rendering settings."))
+}
+
+pub fn extract_threads(lhm: &Mapping) -> Option<usize> {
+    lhm.get(&Value::String("threads".to_owned()))
+        .map(|v| v.as_u64().expect("Number of threads must be an integer.") as usize)
+}
+
+pub fn extract_surfaces(
+    lhm: &Mapping,
+    vop_map: &HashMap<String, Arc<VOP>>,
+) -> Vec<Arc<dyn Surface + Send + Sync>> {
+    let surfaces = lhm
+        .get(&Value::String("surfaces".to_owned()))
+        .expect("No surfaces give")
+        .as_sequence()
+        .expect("Surfaces must be a list.")
+        .to_owned();
+
+    let mut surface_list = Vec::new();
+
+    for s in surfaces.iter() {
+        let mapping = s
+            .as_mapping()
+            .expect("Each surfacemust be given as a mapping.");
+        let surface = match mapping
+            .get(&Value::String("type".to_owned()))
+            .expect("Surface must have a type.")
+            .as_str()
+            .expect("Surface type must be a string.")
+        {
+            "checkerboard" => from_value::<CheckerboardBuilder>(s.to_owned())
+                .expect("Error parsing checkerboard.")
+                .build(vop_map),
+            "rectangle" => from_value::<RectangleBuilder>(s.to_owned())
+                .expect("Error parsing rectangle.")
+                .build(vop_map),
+            "texturedrectangle" => from_value::<TexturedRectangleBuilder>(s.to_owned())
+                .expect("Error parsing textured rectangle.")
+                .build(vop_map),
+            "plane" => from_value::<PlaneBuilder>(s.to_owned())
+                .expect("Error parsing plane.")
+                .build(vop_map),
+            "mandelbrotplane" => from_value::<MandelbrotPlaneBuilder>(s.to_owned())
+                .expect("Error parsing Mandelbrot plane.")
+                .build(vop_map),
+            "sphere" => from_value::<SphereBuilder>(s.to_owned())
+                .expect("Error parsing sphere.")
+                .build(vop_map),
+            "paraboloid" => from_value::<ParaboloidBuilder>(s.to_owned())
+                .expect("Error parsing paraboloid.")
+                .build(vop_map),
+            "cylinder" => from_value::<CylinderBuilder>(s.to_owned())
+                .expect("Error parsing cylinder.")
+                .build(vop_map),
+            "bezier" => from_value::<BezierBuilder>(s.to_owned())
+                .expect("Error parsing Bezier surface.")
+                .build(vop_map),
+            "implicit" => from_value::<ImplicitBuilder>(s.to_owned())
+                .expect("Error parsing implicit surface.")
+                .build(vop_map),
+            _ => panic!("Unknown surface type"),
+        };
+        // moving surfaces are wrapped to follow their motion
+        let surface = match mapping.get(&Value::String("motion".to_owned())) {
+            Some(motion) => Arc::new(Moving::new(
+                surface,
+                from_value::<Motion>(motion.to_owned()).expect("Error parsing motion."),
+            )),
+            None => surface,
+        };
+        // surfaces bounding media that override overlapping ones carry their priority
+        let surface = match mapping.get(&Value::String("priority".to_owned())) {
+            Some(priority) => Arc::new(Prioritized::new(
+                surface,
+                priority
+                    .as_i64()
+                    .expect("Surface priority must be an integer.") as i32,
+            )),
+            None => surface,
+        };
+        surface_list.push(surface);
+    }
+
+    surface_list
+}
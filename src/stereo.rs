@@ -0,0 +1,241 @@
+use {
+    crate::projection::Projection,
+    nalgebra::{Point3, Rotation3, Unit, Vector3},
+    serde::Deserialize,
+    std::path::Path,
+};
+
+/// How the eyes of a stereo rig converge.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoMode {
+    /// Parallel eyes, with their screens shifted so that they meet at the convergence distance.
+    #[default]
+    Parallel,
+    /// Eyes rotated to look at the point at the convergence distance.
+    ToeIn,
+}
+
+/// How the views of both eyes are saved.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoLayout {
+    /// A single image, left view on the left.
+    #[default]
+    SideBySide,
+    /// A single image, left view on top.
+    TopBottom,
+    /// One image per eye, their names suffixed with `_left` and `_right`.
+    Separate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    /// Side of the camera the eye is on, along its right direction.
+    fn side(self) -> f64 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Eye::Left => "left",
+            Eye::Right => "right",
+        }
+    }
+}
+
+/// Stereo rig around the camera origin, rendering a view per eye. With an equirectangular
+/// projection the rig renders omni-directional stereo: every ray leaves from the point of the
+/// circle of eye positions that sees it straight ahead, and the convergence is ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Stereo {
+    /// Distance between the eyes.
+    pub interocular: f64,
+    /// Distance along the gaze at which the views of both eyes meet, at infinity if not given.
+    pub convergence: Option<f64>,
+    #[serde(default)]
+    pub mode: StereoMode,
+    #[serde(default)]
+    pub layout: StereoLayout,
+}
+
+/// Pixels of an image, row by row, along with its number of rows and columns.
+pub type Image = (Vec<[u8; 3]>, usize, usize);
+
+impl Stereo {
+    /// Origin and direction of the ray seen by one eye, from the ray of the camera at the center
+    /// of the rig. `frame` holds the gaze, up and right directions of the camera.
+    pub fn eye_ray(
+        &self,
+        eye: Eye,
+        projection: Projection,
+        frame: [&Unit<Vector3<f64>>; 3],
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+    ) -> (Point3<f64>, Vector3<f64>) {
+        let [gaze, up, right] = frame;
+        let offset = eye.side() * self.interocular / 2.0;
+
+        if projection == Projection::Equirectangular {
+            // tangent to the circle of eye positions, perpendicular to the ray
+            let horizontal = direction - direction.dot(up) * up.into_inner();
+            return match Unit::try_new(horizontal.cross(up), f64::EPSILON) {
+                Some(tangent) => (origin + offset * tangent.into_inner(), direction),
+                None => (origin, direction),
+            };
+        }
+
+        let eye_origin = origin + offset * right.into_inner();
+        match (self.mode, self.convergence) {
+            (StereoMode::Parallel, None) => (eye_origin, direction),
+            (StereoMode::Parallel, Some(convergence)) => {
+                // aim at the point the central ray reaches at the convergence distance
+                let shift = offset * direction.dot(gaze) / convergence;
+                (eye_origin, direction - shift * right.into_inner())
+            }
+            (StereoMode::ToeIn, Some(convergence)) => {
+                let rotation = Rotation3::from_axis_angle(up, (offset / convergence).atan());
+                (eye_origin, rotation * direction)
+            }
+            (StereoMode::ToeIn, None) => panic!("Toe-in stereo needs a convergence distance."),
+        }
+    }
+
+    /// Images to save for the views of both eyes, along with their paths.
+    pub fn images(&self, filepath: &str, left: Image, right: Image) -> Vec<(String, Image)> {
+        let (num_x, num_y) = (left.1, left.2);
+        match self.layout {
+            StereoLayout::SideBySide => {
+                let data = left
+                    .0
+                    .chunks(num_y)
+                    .zip(right.0.chunks(num_y))
+                    .flat_map(|(l, r)| l.iter().chain(r.iter()).copied())
+                    .collect();
+                vec![(filepath.to_owned(), (data, num_x, 2 * num_y))]
+            }
+            StereoLayout::TopBottom => {
+                let data = left.0.into_iter().chain(right.0).collect();
+                vec![(filepath.to_owned(), (data, 2 * num_x, num_y))]
+            }
+            StereoLayout::Separate => vec![
+                (eye_path(filepath, Eye::Left), left),
+                (eye_path(filepath, Eye::Right), right),
+            ],
+        }
+    }
+}
+
+/// Path of the image of one eye, suffixed with the name of the eye before the extension.
+pub fn eye_path(filepath: &str, eye: Eye) -> String {
+    let path = Path::new(filepath);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}_{}.{}", stem, eye.name(), extension),
+        None => format!("{}_{}", stem, eye.name()),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::TOLERANCE};
+
+    fn stereo(mode: StereoMode, convergence: Option<f64>) -> Stereo {
+        Stereo {
+            interocular: 0.5,
+            convergence,
+            mode,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+
+    /// The central rays of both eyes must meet at the convergence distance.
+    #[test]
+    fn eyes_converge() {
+        let (gaze, up, right) = (Vector3::y_axis(), Vector3::z_axis(), Vector3::x_axis());
+        let origin = Point3::new(1.0, 0.0, 0.0);
+        let target = Point3::new(1.0, 4.0, 0.0);
+        for &mode in &[StereoMode::Parallel, StereoMode::ToeIn] {
+            for &eye in &[Eye::Left, Eye::Right] {
+                let (start, direction) = stereo(mode, Some(4.0)).eye_ray(
+                    eye,
+                    Projection::Perspective,
+                    [&gaze, &up, &right],
+                    origin,
+                    gaze.into_inner(),
+                );
+                assert!(((start - origin).norm() - 0.25).abs() < TOLERANCE);
+                assert!((target - start).normalize().cross(&direction).norm() < TOLERANCE);
+            }
+        }
+        // parallel eyes without convergence look straight ahead
+        let (_, direction) = stereo(StereoMode::Parallel, None).eye_ray(
+            Eye::Left,
+            Projection::Perspective,
+            [&gaze, &up, &right],
+            origin,
+            gaze.into_inner(),
+        );
+        assert_eq!(direction, gaze.into_inner());
+    }
+
+    /// Omni-directional stereo rays must leave from the circle of eye positions, sideways.
+    #[test]
+    fn omnidirectional_stereo() {
+        let (gaze, up, right) = (Vector3::y_axis(), Vector3::z_axis(), Vector3::x_axis());
+        let origin = Point3::origin();
+        let direction = Vector3::new(1.0, 1.0, 0.5);
+        let (start, towards) = stereo(StereoMode::Parallel, Some(4.0)).eye_ray(
+            Eye::Right,
+            Projection::Equirectangular,
+            [&gaze, &up, &right],
+            origin,
+            direction,
+        );
+        assert_eq!(towards, direction);
+        assert!(((start - origin).norm() - 0.25).abs() < TOLERANCE);
+        assert!((start - origin).dot(&direction).abs() < TOLERANCE);
+        assert!(start.z.abs() < TOLERANCE);
+        // looking along the gaze, the right eye is on the right
+        let (start, _) = stereo(StereoMode::Parallel, None).eye_ray(
+            Eye::Right,
+            Projection::Equirectangular,
+            [&gaze, &up, &right],
+            origin,
+            gaze.into_inner(),
+        );
+        assert!((start - Point3::new(0.25, 0.0, 0.0)).norm() < TOLERANCE);
+    }
+
+    #[test]
+    fn layouts() {
+        let image = |value| (vec![[value; 3]; 6], 2, 3);
+        let mut rig = stereo(StereoMode::Parallel, None);
+        let side_by_side = rig.images("out.png", image(1), image(2));
+        assert_eq!(side_by_side.len(), 1);
+        let (data, num_x, num_y) = &side_by_side[0].1;
+        assert_eq!((*num_x, *num_y), (2, 6));
+        assert_eq!(data[..6], [[1; 3], [1; 3], [1; 3], [2; 3], [2; 3], [2; 3]]);
+
+        rig.layout = StereoLayout::TopBottom;
+        let top_bottom = rig.images("out.png", image(1), image(2));
+        assert_eq!((top_bottom[0].1 .1, top_bottom[0].1 .2), (4, 3));
+
+        rig.layout = StereoLayout::Separate;
+        let paths: Vec<String> = rig
+            .images("dir/out.png", image(1), image(2))
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!["dir/out_left.png", "dir/out_right.png"]);
+    }
+}
@@ -0,0 +1,553 @@
+pub mod simple;
+pub use simple::BezierBuilder;
+use {
+    super::{pick_closest_intersection, plane::PlaneShape, Intersection, Shape},
+    crate::{Ray, TOLERANCE},
+    nalgebra::{Isometry3, Matrix3, Point2, Point3, Unit, Vector3},
+    std::fs,
+};
+
+/// Maximum number of Newton iterations when refining an intersection or a projection.
+const NEWTON_STEPS: usize = 20;
+
+/// Cubic Bernstein polynomials and their derivatives at t.
+fn bernstein(t: f64) -> ([f64; 4], [f64; 4]) {
+    let s = 1.0 - t;
+    (
+        [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t],
+        [
+            -3.0 * s * s,
+            3.0 * s * s - 6.0 * t * s,
+            6.0 * t * s - 3.0 * t * t,
+            3.0 * t * t,
+        ],
+    )
+}
+
+/// Moller-Trumbore intersection of a line with a triangle. Returns the line parameter and the
+/// barycentric coordinates of the intersection with respect to the 2nd and 3rd vertices.
+fn line_triangle_intersection(
+    origin: &Point3<f64>,
+    direction: &Vector3<f64>,
+    triangle: [&Point3<f64>; 3],
+) -> Option<(f64, f64, f64)> {
+    let e1 = triangle[1] - triangle[0];
+    let e2 = triangle[2] - triangle[0];
+    let p = direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() <= f64::EPSILON {
+        return None;
+    }
+    let s = origin - triangle[0];
+    let a = s.dot(&p) / det;
+    let q = s.cross(&e1);
+    let b = direction.dot(&q) / det;
+    // accept a small margin, since the triangle is only used as a starting guess
+    let margin = 0.1;
+    if a < -margin || b < -margin || a + b > 1.0 + margin {
+        return None;
+    }
+    Some((e2.dot(&q) / det, a, b))
+}
+
+/// Bicubic Bezier patch, defined by a 4x4 grid of control points. The first index runs along the
+/// u parameter, the second along v.
+pub struct BezierPatch {
+    points: [[Point3<f64>; 4]; 4],
+    bounds_min: Point3<f64>,
+    bounds_max: Point3<f64>,
+    resolution: usize,
+    grid: Vec<Point3<f64>>,
+}
+
+impl BezierPatch {
+    /// Create a patch from its control points, precomputing a coarse grid of surface points that
+    /// is used to find starting guesses for the Newton iterations.
+    pub fn new(points: [[Point3<f64>; 4]; 4], resolution: usize) -> Self {
+        let mut bounds_min = points[0][0];
+        let mut bounds_max = points[0][0];
+        // the patch lies within the convex hull of its control points
+        for p in points.iter().flatten() {
+            bounds_min = bounds_min.inf(p);
+            bounds_max = bounds_max.sup(p);
+        }
+        let mut patch = Self {
+            points,
+            bounds_min: bounds_min - Vector3::repeat(TOLERANCE),
+            bounds_max: bounds_max + Vector3::repeat(TOLERANCE),
+            resolution: resolution.max(1),
+            grid: Vec::new(),
+        };
+        let n = patch.resolution;
+        for i in 0..=n {
+            for j in 0..=n {
+                let p = patch.eval(i as f64 / n as f64, j as f64 / n as f64);
+                patch.grid.push(p);
+            }
+        }
+        patch
+    }
+
+    /// Point on the patch at the given parameters.
+    pub fn eval(&self, u: f64, v: f64) -> Point3<f64> {
+        let (bu, _) = bernstein(u);
+        let (bv, _) = bernstein(v);
+        let mut p = Vector3::zeros();
+        for (row, bu) in self.points.iter().zip(bu.iter()) {
+            for (point, bv) in row.iter().zip(bv.iter()) {
+                p += bu * bv * point.coords;
+            }
+        }
+        Point3::from(p)
+    }
+
+    /// Partial derivatives of the patch with respect to u and v.
+    pub fn derivatives(&self, u: f64, v: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let (bu, dbu) = bernstein(u);
+        let (bv, dbv) = bernstein(v);
+        let mut su = Vector3::zeros();
+        let mut sv = Vector3::zeros();
+        for (i, row) in self.points.iter().enumerate() {
+            for (j, point) in row.iter().enumerate() {
+                su += dbu[i] * bv[j] * point.coords;
+                sv += bu[i] * dbv[j] * point.coords;
+            }
+        }
+        (su, sv)
+    }
+
+    /// Analytic (unnormalized) normal of the patch, as the cross product of the partial
+    /// derivatives. Degenerate points, such as the poles of the teapot lid, are handled by
+    /// nudging the parameters towards the center of the patch.
+    pub fn normal(&self, u: f64, v: f64) -> Vector3<f64> {
+        let (su, sv) = self.derivatives(u, v);
+        let normal = su.cross(&sv);
+        if normal.norm_squared() > f64::EPSILON {
+            return normal;
+        }
+        let nudge = 1e-4;
+        let (su, sv) = self.derivatives(
+            u + nudge * (0.5 - u).signum(),
+            v + nudge * (0.5 - v).signum(),
+        );
+        su.cross(&sv)
+    }
+
+    fn line_hits_bounds(&self, origin: &Point3<f64>, direction: &Vector3<f64>) -> bool {
+        let mut t_min = f64::NEG_INFINITY;
+        let mut t_max = f64::INFINITY;
+        for i in 0..3 {
+            if direction[i].abs() <= f64::EPSILON {
+                if origin[i] < self.bounds_min[i] || origin[i] > self.bounds_max[i] {
+                    return false;
+                }
+            } else {
+                let t0 = (self.bounds_min[i] - origin[i]) / direction[i];
+                let t1 = (self.bounds_max[i] - origin[i]) / direction[i];
+                t_min = t_min.max(t0.min(t1));
+                t_max = t_max.min(t0.max(t1));
+            }
+        }
+        t_min <= t_max && t_max >= 0.0
+    }
+
+    /// Refine an approximate line intersection (t, u, v) by solving S(u, v) = o + t * d with
+    /// Newton's method. Returns None if the iteration diverges or leaves the patch.
+    fn newton_intersection(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Vector3<f64>,
+        mut t: f64,
+        mut u: f64,
+        mut v: f64,
+    ) -> Option<(f64, f64, f64)> {
+        for _ in 0..NEWTON_STEPS {
+            let residual = self.eval(u, v) - (origin + t * direction);
+            if residual.norm_squared() <= TOLERANCE.powi(4) {
+                break;
+            }
+            let (su, sv) = self.derivatives(u, v);
+            let jacobian = Matrix3::from_columns(&[-direction, su, sv]);
+            let step = jacobian.lu().solve(&(-residual))?;
+            t += step[0];
+            u = (u + step[1]).clamp(-0.5, 1.5);
+            v = (v + step[2]).clamp(-0.5, 1.5);
+        }
+        let residual = self.eval(u, v) - (origin + t * direction);
+        let range = -TOLERANCE..=1.0 + TOLERANCE;
+        if residual.norm() <= TOLERANCE && range.contains(&u) && range.contains(&v) {
+            Some((t, u.clamp(0.0, 1.0), v.clamp(0.0, 1.0)))
+        } else {
+            None
+        }
+    }
+
+    /// All intersections of a line with the patch, as points along with their (u, v) parameters.
+    pub fn line_intersection(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Vector3<f64>,
+    ) -> Vec<(Point3<f64>, f64, f64)> {
+        if !self.line_hits_bounds(origin, direction) {
+            return vec![];
+        }
+
+        let n = self.resolution;
+        let h = 1.0 / n as f64;
+        let mut intersections: Vec<(Point3<f64>, f64, f64)> = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                let p00 = &self.grid[i * (n + 1) + j];
+                let p10 = &self.grid[(i + 1) * (n + 1) + j];
+                let p01 = &self.grid[i * (n + 1) + j + 1];
+                let p11 = &self.grid[(i + 1) * (n + 1) + j + 1];
+
+                // each grid cell is split into two triangles, giving starting guesses for (u, v)
+                let guesses = [
+                    line_triangle_intersection(origin, direction, [p00, p10, p01])
+                        .map(|(t, a, b)| (t, (i as f64 + a) * h, (j as f64 + b) * h)),
+                    line_triangle_intersection(origin, direction, [p11, p01, p10])
+                        .map(|(t, a, b)| (t, (i as f64 + 1.0 - a) * h, (j as f64 + 1.0 - b) * h)),
+                ];
+                for (t, u, v) in guesses.iter().flatten() {
+                    if let Some((t, u, v)) = self.newton_intersection(origin, direction, *t, *u, *v)
+                    {
+                        let p = self.eval(u, v);
+                        if t >= 0.0
+                            && intersections
+                                .iter()
+                                .all(|(q, _, _)| (p - q).norm() > TOLERANCE)
+                        {
+                            intersections.push((p, u, v));
+                        }
+                    }
+                }
+            }
+        }
+        intersections
+    }
+
+    /// Find the parameters of the point of the patch closest to the given point, along with the
+    /// squared distance between the two.
+    pub fn project(&self, point: &Point3<f64>) -> (f64, f64, f64) {
+        // start from the closest grid point
+        let n = self.resolution;
+        let (index, _) = self
+            .grid
+            .iter()
+            .map(|p| (p - point).norm_squared())
+            .enumerate()
+            .fold(
+                (0, f64::INFINITY),
+                |best, (i, d)| if d < best.1 { (i, d) } else { best },
+            );
+        let mut u = (index / (n + 1)) as f64 / n as f64;
+        let mut v = (index % (n + 1)) as f64 / n as f64;
+
+        // Gauss-Newton minimization of |S(u, v) - p|^2
+        for _ in 0..NEWTON_STEPS {
+            let residual = self.eval(u, v) - point;
+            let (su, sv) = self.derivatives(u, v);
+            let (a, b, c) = (su.dot(&su), su.dot(&sv), sv.dot(&sv));
+            let det = a * c - b * b;
+            if det.abs() <= f64::EPSILON {
+                break;
+            }
+            let (gu, gv) = (su.dot(&residual), sv.dot(&residual));
+            let du = -(c * gu - b * gv) / det;
+            let dv = -(a * gv - b * gu) / det;
+            u = (u + du).clamp(0.0, 1.0);
+            v = (v + dv).clamp(0.0, 1.0);
+            if du.abs() + dv.abs() <= f64::EPSILON {
+                break;
+            }
+        }
+        (u, v, (self.eval(u, v) - point).norm_squared())
+    }
+}
+
+/// Surface made of one or more bicubic Bezier patches. Control points are given in local
+/// coordinates, which are mapped to global ones through the reference plane (origin, normal and
+/// orientation), with the local Z axis along the normal and the local Y axis along the
+/// orientation.
+pub struct BezierShape {
+    plane: PlaneShape,
+    patches: Vec<BezierPatch>,
+    pub origin: Point3<f64>,
+    pub flip_normals: bool,
+}
+
+impl BezierShape {
+    pub fn new(
+        patches: Vec<BezierPatch>,
+        origin: Point3<f64>,
+        normal: Vector3<f64>,
+        orientation: Vector3<f64>,
+        flip_normals: bool,
+    ) -> Self {
+        Self {
+            plane: PlaneShape::new(origin, normal, Some(orientation)),
+            patches,
+            origin,
+            flip_normals,
+        }
+    }
+
+    /// Find the patch closest to a point and the patch parameters of the point.
+    fn closest_patch(&self, point: &Point3<f64>) -> Option<(&BezierPatch, f64, f64, f64)> {
+        self.patches
+            .iter()
+            .map(|patch| {
+                let (u, v, dsq) = patch.project(point);
+                (patch, u, v, dsq)
+            })
+            .min_by(|a, b| a.3.partial_cmp(&b.3).unwrap())
+    }
+
+    /// Normal of one of the patches, in global coordinates.
+    fn global_normal(&self, patch: &BezierPatch, u: f64, v: f64) -> Unit<Vector3<f64>> {
+        let normal = patch.normal(u, v);
+        let normal = if self.flip_normals { -normal } else { normal };
+        Unit::new_normalize(self.to_global() * normal)
+    }
+}
+
+impl Shape for BezierShape {
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
+        let origin: Point3<f64> = self.to_local() * ray.origin;
+        let direction: Vector3<f64> = self.to_local() * ray.direction;
+        let intersections: Vec<Intersection> = self
+            .patches
+            .iter()
+            .flat_map(|patch| {
+                patch
+                    .line_intersection(&origin, &direction)
+                    .into_iter()
+                    .map(move |(p, u, v)| (patch, p, u, v))
+            })
+            .map(|(patch, p, u, v)| {
+                Intersection::new(
+                    ray,
+                    self.to_global() * p,
+                    self.global_normal(patch, u, v),
+                    Point2::new(u, v),
+                )
+            })
+            .collect();
+        pick_closest_intersection(intersections)
+    }
+    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
+        let local_point: Point3<f64> = self.to_local() * point;
+        let (patch, u, v, _) = self
+            .closest_patch(&local_point)
+            .expect("Bezier surface has no patches.");
+        self.global_normal(patch, u, v)
+    }
+    fn contains(&self, point: &Point3<f64>) -> bool {
+        let local_point: Point3<f64> = self.to_local() * point;
+        self.closest_patch(&local_point)
+            .is_some_and(|(_, _, _, dsq)| dsq <= TOLERANCE)
+    }
+    fn origin(&self) -> &Point3<f64> {
+        &self.origin
+    }
+    fn to_local(&self) -> &Isometry3<f64> {
+        self.plane.to_local()
+    }
+    fn to_global(&self) -> &Isometry3<f64> {
+        self.plane.to_global()
+    }
+}
+
+/// Load patches from a file in the format of the Utah teapot data set: the number of patches,
+/// followed by one line of 16 (1-based) vertex indices per patch, then the number of vertices,
+/// followed by one line of 3 coordinates per vertex. Values may be separated by commas and/or
+/// whitespace.
+pub fn load_patches_from_file(filepath: &str) -> Vec<[[Point3<f64>; 4]; 4]> {
+    let contents = fs::read_to_string(filepath)
+        .unwrap_or_else(|_| panic!("Could not read patch file {}", filepath));
+    parse_patches(&contents).unwrap_or_else(|e| panic!("Invalid patch file {}: {}", filepath, e))
+}
+
+fn parse_patches(contents: &str) -> Result<Vec<[[Point3<f64>; 4]; 4]>, String> {
+    let mut values = contents
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty());
+    let mut next_value = |what: &str| -> Result<&str, String> {
+        values
+            .next()
+            .ok_or_else(|| format!("unexpected end of file, expected {}", what))
+    };
+
+    let num_patches: usize = next_value("number of patches")?
+        .parse()
+        .map_err(|_| "invalid number of patches".to_owned())?;
+    let mut indices: Vec<[usize; 16]> = Vec::with_capacity(num_patches);
+    for _ in 0..num_patches {
+        let mut patch = [0; 16];
+        for index in patch.iter_mut() {
+            *index = next_value("vertex index")?
+                .parse()
+                .map_err(|_| "invalid vertex index".to_owned())?;
+        }
+        indices.push(patch);
+    }
+
+    let num_vertices: usize = next_value("number of vertices")?
+        .parse()
+        .map_err(|_| "invalid number of vertices".to_owned())?;
+    let mut vertices: Vec<Point3<f64>> = Vec::with_capacity(num_vertices);
+    for _ in 0..num_vertices {
+        let mut coords = [0.0; 3];
+        for c in coords.iter_mut() {
+            *c = next_value("vertex coordinate")?
+                .parse()
+                .map_err(|_| "invalid vertex coordinate".to_owned())?;
+        }
+        vertices.push(Point3::from_slice(&coords));
+    }
+
+    indices
+        .into_iter()
+        .map(|patch| {
+            let mut points = [[Point3::origin(); 4]; 4];
+            for (k, index) in patch.iter().enumerate() {
+                points[k / 4][k % 4] = *vertices
+                    .get(index.wrapping_sub(1))
+                    .ok_or_else(|| format!("vertex index {} out of range", index))?;
+            }
+            Ok(points)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{surface::tests::bounce_off, SOP, VOP};
+    use std::sync::Arc;
+
+    /// Patch approximating the paraboloid z = x^2 + y^2 over [-1, 1]^2.
+    fn bowl() -> BezierPatch {
+        let mut points = [[Point3::origin(); 4]; 4];
+        let coords = [-1.0, -1.0 / 3.0, 1.0 / 3.0, 1.0];
+        // control point heights that reproduce x^2 exactly along each axis
+        let heights = [1.0, -1.0 / 3.0, -1.0 / 3.0, 1.0];
+        for (i, row) in points.iter_mut().enumerate() {
+            for (j, point) in row.iter_mut().enumerate() {
+                *point = Point3::new(coords[i], coords[j], heights[i] + heights[j]);
+            }
+        }
+        BezierPatch::new(points, 4)
+    }
+
+    fn flat_patch() -> BezierPatch {
+        let mut points = [[Point3::origin(); 4]; 4];
+        for (i, row) in points.iter_mut().enumerate() {
+            for (j, point) in row.iter_mut().enumerate() {
+                *point = Point3::new(i as f64, j as f64, 0.0);
+            }
+        }
+        BezierPatch::new(points, 4)
+    }
+
+    fn downwards_ray(origin: Point3<f64>) -> Ray {
+        Ray {
+            origin,
+            direction: -Vector3::z(),
+            vop: Arc::new(VOP::new(1.0, [0.0; 3])),
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        }
+    }
+
+    #[test]
+    fn bowl_evaluation() {
+        let patch = bowl();
+        for &(u, v) in &[(0.5, 0.5), (0.25, 0.75), (0.0, 1.0)] {
+            let p = patch.eval(u, v);
+            assert!((p.z - (p.x.powi(2) + p.y.powi(2))).abs() <= TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn bowl_intersection_and_normal() {
+        let shape = BezierShape::new(
+            vec![bowl()],
+            Point3::origin(),
+            Vector3::z(),
+            Vector3::y(),
+            false,
+        );
+        let intersection = shape
+            .intersection(&downwards_ray(Point3::new(0.5, 0.25, 10.0)))
+            .unwrap();
+        let p = intersection.point;
+        assert!((p - Point3::new(0.5, 0.25, 0.3125)).norm() <= TOLERANCE);
+        assert!(shape.contains(&p));
+
+        let expected = Vector3::new(-2.0 * p.x, -2.0 * p.y, 1.0).normalize();
+        assert!((intersection.normal.into_inner() - expected).norm() <= TOLERANCE);
+        let normal = shape.unchecked_normal_at(&p);
+        assert!((normal.into_inner() - expected).norm() <= TOLERANCE);
+    }
+
+    #[test]
+    fn flat_patch_miss() {
+        let patch = flat_patch();
+        assert!(patch
+            .line_intersection(&Point3::new(3.5, 1.0, 1.0), &-Vector3::z())
+            .is_empty());
+        assert!(patch
+            .line_intersection(&Point3::new(1.0, 1.0, -1.0), &-Vector3::z())
+            .is_empty());
+        let intersections = patch.line_intersection(&Point3::new(1.0, 2.0, 1.0), &-Vector3::z());
+        assert_eq!(intersections.len(), 1);
+        assert!((intersections[0].0 - Point3::new(1.0, 2.0, 0.0)).norm() <= TOLERANCE);
+    }
+
+    #[test]
+    fn parse_patch_file() {
+        let mut contents = "1\n".to_owned();
+        contents += &(1..=16)
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        contents += "\n16\n";
+        for i in 0..4 {
+            for j in 0..4 {
+                contents += &format!("{}, {}, 0.0\n", i, j);
+            }
+        }
+        let patches = parse_patches(&contents).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0][2][3], Point3::new(2.0, 3.0, 0.0));
+        assert!(parse_patches("1\n1,2,3").is_err());
+    }
+
+    /// Rays reflected off the bowl from outside at a grazing angle must not hit it again where
+    /// they left it, and rays refracted into it must next hit it across.
+    #[test]
+    fn grazing_bounces() {
+        let surface = |sop| super::simple::Bezier {
+            geometry: BezierShape::new(
+                // fine enough a grid for the starting guesses of grazing rays
+                vec![BezierPatch::new(bowl().points, 32)],
+                Point3::origin(),
+                Vector3::z(),
+                Vector3::y(),
+                false,
+            ),
+            sop,
+            vop_above: Arc::new(VOP::new(1.5, [0.0; 3])),
+            vop_below: Arc::new(VOP::new(1.0, [0.0; 3])),
+        };
+        let origin = Point3::new(-10.0, 0.53, 0.3);
+        let (_, next) = bounce_off(&surface(SOP::Reflect), origin, Vector3::x());
+        assert!(next.is_none());
+        let (point, next) = bounce_off(&surface(SOP::Refract), origin, Vector3::x());
+        assert!((next.unwrap() - point).norm() > 0.1);
+    }
+}
@@ -0,0 +1,125 @@
+use {
+    super::{
+        super::{Hit, Shape, Surface, SurfaceBuilder},
+        load_patches_from_file, BezierPatch, BezierShape,
+    },
+    crate::{Ray, SOP, VOP},
+    collections::HashMap,
+    nalgebra::{Point3, Vector3},
+    serde::Deserialize,
+    std::collections,
+    std::sync::Arc,
+};
+
+pub struct Bezier {
+    pub geometry: BezierShape,
+    pub sop: SOP,
+    pub vop_above: Arc<VOP>,
+    pub vop_below: Arc<VOP>,
+}
+
+/// Bezier surface, given either as a list of patches of 16 control points each (row by row), or
+/// as a path to a patch file in the Utah teapot format, or both.
+#[derive(Deserialize)]
+pub struct BezierBuilder {
+    #[serde(default)]
+    pub patches: Vec<Vec<[f64; 3]>>,
+    pub file: Option<String>,
+    #[serde(default = "default_origin")]
+    pub origin: [f64; 3],
+    #[serde(default = "default_normal")]
+    pub normal: [f64; 3],
+    #[serde(default = "default_orientation")]
+    pub orientation: [f64; 3],
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default = "default_resolution")]
+    pub resolution: usize,
+    #[serde(default)]
+    pub flip_normals: bool,
+    pub sop: SOP,
+    pub vop_below: String,
+    pub vop_above: String,
+}
+
+fn default_origin() -> [f64; 3] {
+    [0.0, 0.0, 0.0]
+}
+
+fn default_normal() -> [f64; 3] {
+    [0.0, 0.0, 1.0]
+}
+
+fn default_orientation() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_resolution() -> usize {
+    4
+}
+
+impl Surface for Bezier {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry
+            .intersection(ray)
+            .map(|i| Hit::new(i, ray, self.sop, &self.vop_above, &self.vop_below))
+    }
+}
+
+impl SurfaceBuilder for BezierBuilder {
+    fn build(self, vop_map: &HashMap<String, Arc<VOP>>) -> Arc<dyn Surface + Send + Sync> {
+        let mut control_points: Vec<[[Point3<f64>; 4]; 4]> = self
+            .patches
+            .iter()
+            .map(|patch| {
+                if patch.len() != 16 {
+                    panic!("Bezier patches must have 16 control points.")
+                }
+                let mut points = [[Point3::origin(); 4]; 4];
+                for (k, p) in patch.iter().enumerate() {
+                    points[k / 4][k % 4] = Point3::from_slice(p);
+                }
+                points
+            })
+            .collect();
+        if let Some(filepath) = &self.file {
+            control_points.extend(load_patches_from_file(filepath));
+        }
+        if control_points.is_empty() {
+            panic!("Bezier surface must have at least one patch.")
+        }
+
+        let patches: Vec<BezierPatch> = control_points
+            .into_iter()
+            .map(|mut points| {
+                for p in points.iter_mut().flatten() {
+                    *p *= self.scale;
+                }
+                BezierPatch::new(points, self.resolution)
+            })
+            .collect();
+
+        Arc::new(Bezier {
+            geometry: BezierShape::new(
+                patches,
+                Point3::from_slice(&self.origin),
+                Vector3::from_row_slice(&self.normal),
+                Vector3::from_row_slice(&self.orientation),
+                self.flip_normals,
+            ),
+            sop: self.sop,
+            vop_above: vop_map
+                .get(&self.vop_above)
+                .expect("No VOP above mapping found.")
+                .clone(),
+            vop_below: vop_map
+                .get(&self.vop_below)
+                .expect("No VOP above mapping found.")
+                .clone(),
+        })
+    }
+}
@@ -4,9 +4,10 @@ use crate::TOLERANCE;
 pub use simple::CylinderBuilder;
 
 use {
-    super::{disk::DiskShape, Shape},
+    super::{disk::DiskShape, Intersection, Shape},
     crate::Ray,
-    nalgebra::{Isometry3, Point3, Unit, Vector3},
+    nalgebra::{Isometry3, Point2, Point3, Unit, Vector3},
+    std::f64::consts::PI,
 };
 
 /// The direction of the cylinder is bottom -> top, while the normals of each disk are defined
@@ -45,16 +46,16 @@ impl CylinderShape {
 
 impl Shape for CylinderShape {
     #[allow(clippy::many_single_char_names)]
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        let mut intersections: Vec<Point3<f64>> = Vec::new();
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
+        let mut intersections: Vec<Intersection> = Vec::new();
 
         // TODO: optimize this
         // check disk intersections
-        if let Some(p) = self.top_disk.intersection(ray) {
-            intersections.push(p);
+        if let Some(i) = self.top_disk.intersection(ray) {
+            intersections.push(i);
         }
-        if let Some(p) = self.bottom_disk.intersection(ray) {
-            intersections.push(p);
+        if let Some(i) = self.bottom_disk.intersection(ray) {
+            intersections.push(i);
         }
 
         // if ray has already intersected both disks, it cannot intersect cylindrical surface
@@ -74,26 +75,35 @@ impl Shape for CylinderShape {
             let beta: f64 = b.powi(2) - 4.0 * a * c;
 
             // -height <= oz + lambda * dz <= 0
-            let mut lambda: f64;
-            let mut z: f64;
             if beta >= 0.0 {
-                // + case
-                lambda = (-b + beta.sqrt()) / (2.0 * a);
-                z = o.z + lambda * d.z;
-                if z < 0.0 && -self.height < z {
-                    intersections.push(self.to_global() * (o + lambda * d));
-                }
-                // - case
-                lambda = (-b - beta.sqrt()) / (2.0 * a);
-                z = o.z + lambda * d.z;
-                if z < 0.0 && -self.height < z {
-                    intersections.push(self.to_global() * (o + lambda * d));
+                // + and - cases
+                for lambda in [
+                    (-b + beta.sqrt()) / (2.0 * a),
+                    (-b - beta.sqrt()) / (2.0 * a),
+                ] {
+                    let local_point: Point3<f64> = o + lambda * d;
+                    if local_point.z < 0.0 && -self.height < local_point.z {
+                        // side normal and (angle, height) coordinates from the local point
+                        let normal = Unit::new_normalize(
+                            self.to_global() * Vector3::new(local_point.x, local_point.y, 0.0),
+                        );
+                        let uv = Point2::new(
+                            local_point.y.atan2(local_point.x) / (2.0 * PI) + 0.5,
+                            -local_point.z / self.height,
+                        );
+                        intersections.push(Intersection {
+                            t: lambda,
+                            point: self.to_global() * local_point,
+                            normal,
+                            uv,
+                        });
+                    }
                 }
             }
         }
 
         // return closest intersection in global coords
-        pick_closest_intersection(intersections, ray)
+        pick_closest_intersection(intersections)
     }
     fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
         let local_point: Point3<f64> = self.to_local() * point;
@@ -191,59 +201,104 @@ mod tests {
 
         #[test]
         fn bottom_disk_surface() {
-            let air = VOP {
-                ior: 1.0,
-                abs: [0.0, 0.0, 0.0],
-            };
+            let air = VOP::new(1.0, [0.0, 0.0, 0.0]);
             let ray = Ray {
                 origin: Point3::new(0.25, 0.25, -1.0),
                 direction: Vector3::z(),
                 vop: Arc::new(air),
                 abs: [0.0, 0.0, 0.0],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
             let cyl = cylinder();
             assert!(
-                (cyl.intersection(&ray).unwrap() - Point3::new(0.25, 0.25, 0.0)).norm_squared()
+                (cyl.intersection(&ray).unwrap().point - Point3::new(0.25, 0.25, 0.0))
+                    .norm_squared()
                     <= TOLERANCE
             );
         }
 
         #[test]
         fn top_disk_surface() {
-            let air = VOP {
-                ior: 1.0,
-                abs: [0.0, 0.0, 0.0],
-            };
+            let air = VOP::new(1.0, [0.0, 0.0, 0.0]);
             let ray = Ray {
                 origin: Point3::new(0.25, 0.25, 5.0),
                 direction: Vector3::z(),
                 vop: Arc::new(air),
                 abs: [0.0, 0.0, 0.0],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
             let cyl = cylinder();
             assert!(
-                (cyl.intersection(&ray).unwrap() - Point3::new(0.25, 0.25, 10.0)).norm_squared()
+                (cyl.intersection(&ray).unwrap().point - Point3::new(0.25, 0.25, 10.0))
+                    .norm_squared()
                     <= TOLERANCE
             );
         }
 
         #[test]
         fn side_intersection() {
-            let air = VOP {
-                ior: 1.0,
-                abs: [0.0, 0.0, 0.0],
-            };
+            let air = VOP::new(1.0, [0.0, 0.0, 0.0]);
             let ray = Ray {
                 origin: Point3::new(-10.0, 0.0, 5.0),
                 direction: Vector3::x(),
                 vop: Arc::new(air),
                 abs: [0.0, 0.0, 0.0],
+                time: 0.0,
+                media: Default::default(),
+                path: Default::default(),
             };
             let cyl = cylinder();
             assert!(
-                (cyl.intersection(&ray).unwrap() - Point3::new(-1.0, 0.0, 5.0)).norm_squared()
+                (cyl.intersection(&ray).unwrap().point - Point3::new(-1.0, 0.0, 5.0))
+                    .norm_squared()
                     <= TOLERANCE
             );
         }
     }
+
+    #[cfg(test)]
+    mod grazing {
+        use super::*;
+        use crate::{surface::tests::bounce_off, SOP, VOP};
+        use std::sync::Arc;
+
+        fn surface(sop: SOP) -> super::super::simple::Cylinder {
+            super::super::simple::Cylinder {
+                geometry: cylinder(),
+                sop,
+                vop_above: Arc::new(VOP::new(1.0, [0.0; 3])),
+                vop_below: Arc::new(VOP::new(1.5, [0.0; 3])),
+            }
+        }
+
+        /// Rays reflected off the side at a grazing angle must not hit it again where they left
+        /// it, and rays refracted into it must next hit it across.
+        #[test]
+        fn side() {
+            let origin = Point3::new(-10.0, 0.999, 5.0);
+            let (_, next) = bounce_off(&surface(SOP::Reflect), origin, Vector3::x());
+            assert!(next.is_none());
+            let (point, next) = bounce_off(&surface(SOP::Refract), origin, Vector3::x());
+            assert!((next.unwrap() - point).norm() > 0.5);
+        }
+
+        /// Rays grazing the top disk right by its rim must leave it when reflected, and when
+        /// refracted must next hit the side just below the rim, not the disk again.
+        #[test]
+        fn cap_rim() {
+            let origin = Point3::new(-10.0, 0.0, 10.01);
+            let direction = Vector3::new(10.999, 0.0, -0.01);
+            let (point, next) = bounce_off(&surface(SOP::Reflect), origin, direction);
+            assert!((point - Point3::new(0.999, 0.0, 10.0)).norm() <= 1e-6);
+            assert!(next.is_none());
+            let (_, next) = bounce_off(&surface(SOP::Refract), origin, direction);
+            let next = next.unwrap();
+            assert!((next.x.hypot(next.y) - 1.0).abs() <= 1e-6);
+            assert!(next.z < 10.0 - 1e-4);
+        }
+    }
 }
@@ -1,5 +1,5 @@
 use {
-    super::{plane::PlaneShape, Shape},
+    super::{plane::PlaneShape, Intersection, Shape},
     crate::Ray,
     nalgebra::{Isometry3, Point3, Unit, Vector3},
 };
@@ -24,10 +24,10 @@ impl DiskShape {
 }
 
 impl Shape for DiskShape {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        if let Some(p) = self.plane.intersection(ray) {
-            if (p - self.origin).norm() <= self.radius {
-                return Some(p);
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
+        if let Some(i) = self.plane.intersection(ray) {
+            if (i.point - self.origin).norm() <= self.radius {
+                return Some(i);
             };
         }
         None
@@ -63,33 +63,36 @@ mod tests {
     #[test]
     fn test_intersection() {
         let plane = xy_circle();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(0.0, 0.8, -1.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(plane.intersects(&ray));
-        assert_eq!(plane.intersection(&ray), Some(Point3::new(0.0, 0.8, 0.0)));
+        assert_eq!(
+            plane.intersection(&ray).map(|i| i.point),
+            Some(Point3::new(0.0, 0.8, 0.0))
+        );
     }
     #[test]
     fn test_no_intersection() {
         let plane = xy_circle();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(1.2, 0.0, -1.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(!plane.intersects(&ray));
-        assert_eq!(plane.intersection(&ray), None);
+        assert!(plane.intersection(&ray).is_none());
     }
 }
@@ -0,0 +1,407 @@
+//! A small parser and evaluator for scalar expressions of the form f(x, y, z), used to describe
+//! implicit surfaces directly in the configuration file.
+//!
+//! Supported syntax:
+//! * numbers (`1`, `0.5`, `2e-3`), the variables `x`, `y`, `z` and the constants `pi` and `e`
+//! * binary operators `+ - * / ^` (with `^` being right-associative) and unary `-` / `+`
+//! * parentheses and the functions `sin cos tan asin acos atan sinh cosh tanh sqrt cbrt abs exp
+//!   ln log sign floor ceil` of one argument, and `min max pow atan2` of two arguments
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryFunction {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sqrt,
+    Cbrt,
+    Abs,
+    Exp,
+    Ln,
+    Log,
+    Sign,
+    Floor,
+    Ceil,
+}
+
+impl UnaryFunction {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Self::Sin,
+            "cos" => Self::Cos,
+            "tan" => Self::Tan,
+            "asin" => Self::Asin,
+            "acos" => Self::Acos,
+            "atan" => Self::Atan,
+            "sinh" => Self::Sinh,
+            "cosh" => Self::Cosh,
+            "tanh" => Self::Tanh,
+            "sqrt" => Self::Sqrt,
+            "cbrt" => Self::Cbrt,
+            "abs" => Self::Abs,
+            "exp" => Self::Exp,
+            "ln" => Self::Ln,
+            "log" => Self::Log,
+            "sign" => Self::Sign,
+            "floor" => Self::Floor,
+            "ceil" => Self::Ceil,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, a: f64) -> f64 {
+        match self {
+            Self::Sin => a.sin(),
+            Self::Cos => a.cos(),
+            Self::Tan => a.tan(),
+            Self::Asin => a.asin(),
+            Self::Acos => a.acos(),
+            Self::Atan => a.atan(),
+            Self::Sinh => a.sinh(),
+            Self::Cosh => a.cosh(),
+            Self::Tanh => a.tanh(),
+            Self::Sqrt => a.sqrt(),
+            Self::Cbrt => a.cbrt(),
+            Self::Abs => a.abs(),
+            Self::Exp => a.exp(),
+            Self::Ln => a.ln(),
+            Self::Log => a.log10(),
+            Self::Sign => a.signum(),
+            Self::Floor => a.floor(),
+            Self::Ceil => a.ceil(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryFunction {
+    Min,
+    Max,
+    Pow,
+    Atan2,
+}
+
+impl BinaryFunction {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "min" => Self::Min,
+            "max" => Self::Max,
+            "pow" => Self::Pow,
+            "atan2" => Self::Atan2,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Self::Min => a.min(b),
+            Self::Max => a.max(b),
+            Self::Pow => a.powf(b),
+            Self::Atan2 => a.atan2(b),
+        }
+    }
+}
+
+/// Node of a parsed expression tree.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Constant(f64),
+    X,
+    Y,
+    Z,
+    Negate(Box<Node>),
+    Add(Box<Node>, Box<Node>),
+    Subtract(Box<Node>, Box<Node>),
+    Multiply(Box<Node>, Box<Node>),
+    Divide(Box<Node>, Box<Node>),
+    Power(Box<Node>, Box<Node>),
+    Unary(UnaryFunction, Box<Node>),
+    Binary(BinaryFunction, Box<Node>, Box<Node>),
+}
+
+/// Scalar expression of the coordinates x, y and z.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+}
+
+impl Expression {
+    /// Parse an expression from a string.
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let root = parser.expression()?;
+        match parser.peek() {
+            None => Ok(Self { root }),
+            Some(t) => Err(ParseError(format!("unexpected token {:?}", t))),
+        }
+    }
+
+    /// Evaluate the expression at the given coordinates.
+    pub fn eval(&self, x: f64, y: f64, z: f64) -> f64 {
+        self.root.eval(x, y, z)
+    }
+}
+
+impl Node {
+    fn eval(&self, x: f64, y: f64, z: f64) -> f64 {
+        match self {
+            Self::Constant(c) => *c,
+            Self::X => x,
+            Self::Y => y,
+            Self::Z => z,
+            Self::Negate(a) => -a.eval(x, y, z),
+            Self::Add(a, b) => a.eval(x, y, z) + b.eval(x, y, z),
+            Self::Subtract(a, b) => a.eval(x, y, z) - b.eval(x, y, z),
+            Self::Multiply(a, b) => a.eval(x, y, z) * b.eval(x, y, z),
+            Self::Divide(a, b) => a.eval(x, y, z) / b.eval(x, y, z),
+            Self::Power(a, b) => match **b {
+                // integer powers are both faster and defined for negative bases
+                Self::Constant(n) if n.fract() == 0.0 && n.abs() <= i32::MAX as f64 => {
+                    a.eval(x, y, z).powi(n as i32)
+                }
+                _ => a.eval(x, y, z).powf(b.eval(x, y, z)),
+            },
+            Self::Unary(f, a) => f.apply(a.eval(x, y, z)),
+            Self::Binary(f, a, b) => f.apply(a.eval(x, y, z), b.eval(x, y, z)),
+        }
+    }
+}
+
+/// Error raised when an expression cannot be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Operator(char),
+    OpenParen,
+    CloseParen,
+    Comma,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // scientific notation, e.g. 1e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(
+                literal
+                    .parse()
+                    .map_err(|_| ParseError(format!("invalid number {}", literal)))?,
+            ));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Operator(c),
+                '(' => Token::OpenParen,
+                ')' => Token::CloseParen,
+                ',' => Token::Comma,
+                _ => return Err(ParseError(format!("unexpected character {:?}", c))),
+            });
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser with the usual operator precedence.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        match self.next() {
+            Some(ref t) if *t == expected => Ok(()),
+            Some(t) => Err(ParseError(format!(
+                "expected {:?}, found {:?}",
+                expected, t
+            ))),
+            None => Err(ParseError(format!("expected {:?}, found end", expected))),
+        }
+    }
+
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Operator(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.position += 1;
+            let rhs = self.term()?;
+            lhs = if op == '+' {
+                Node::Add(Box::new(lhs), Box::new(rhs))
+            } else {
+                Node::Subtract(Box::new(lhs), Box::new(rhs))
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, ParseError> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Operator(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.position += 1;
+            let rhs = self.unary()?;
+            lhs = if op == '*' {
+                Node::Multiply(Box::new(lhs), Box::new(rhs))
+            } else {
+                Node::Divide(Box::new(lhs), Box::new(rhs))
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        match self.peek() {
+            Some(Token::Operator('-')) => {
+                self.position += 1;
+                Ok(Node::Negate(Box::new(self.unary()?)))
+            }
+            Some(Token::Operator('+')) => {
+                self.position += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    /// power := atom ('^' unary)?
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.atom()?;
+        if let Some(Token::Operator('^')) = self.peek() {
+            self.position += 1;
+            let exponent = self.unary()?;
+            return Ok(Node::Power(Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    /// atom := number | variable | constant | function '(' arguments ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Node, ParseError> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Node::Constant(n)),
+            Some(Token::OpenParen) => {
+                let inner = self.expression()?;
+                self.expect(Token::CloseParen)?;
+                Ok(inner)
+            }
+            Some(Token::Identifier(name)) => match name.as_str() {
+                "x" => Ok(Node::X),
+                "y" => Ok(Node::Y),
+                "z" => Ok(Node::Z),
+                "pi" => Ok(Node::Constant(std::f64::consts::PI)),
+                "e" => Ok(Node::Constant(std::f64::consts::E)),
+                _ => {
+                    if let Some(f) = UnaryFunction::from_name(&name) {
+                        self.expect(Token::OpenParen)?;
+                        let a = self.expression()?;
+                        self.expect(Token::CloseParen)?;
+                        Ok(Node::Unary(f, Box::new(a)))
+                    } else if let Some(f) = BinaryFunction::from_name(&name) {
+                        self.expect(Token::OpenParen)?;
+                        let a = self.expression()?;
+                        self.expect(Token::Comma)?;
+                        let b = self.expression()?;
+                        self.expect(Token::CloseParen)?;
+                        Ok(Node::Binary(f, Box::new(a), Box::new(b)))
+                    } else {
+                        Err(ParseError(format!("unknown identifier {}", name)))
+                    }
+                }
+            },
+            Some(t) => Err(ParseError(format!("unexpected token {:?}", t))),
+            None => Err(ParseError("unexpected end of expression".to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, x: f64, y: f64, z: f64) -> f64 {
+        Expression::parse(source).unwrap().eval(x, y, z)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0, 0.0, 0.0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, 0.0, 0.0), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0, 0.0, 0.0), 512.0);
+        assert_eq!(eval("-x^2", 3.0, 0.0, 0.0), -9.0);
+        assert_eq!(eval("8 / 2 / 2", 0.0, 0.0, 0.0), 2.0);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        assert_eq!(eval("x^2 + y^2 + z^2 - 1", 1.0, 2.0, 3.0), 13.0);
+        assert!((eval("sin(pi / 2) + max(x, y)", 1.0, 2.0, 0.0) - 3.0).abs() <= 1e-12);
+        assert_eq!(eval("2e-1 * 10", 0.0, 0.0, 0.0), 2.0);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(Expression::parse("x +").is_err());
+        assert!(Expression::parse("foo(x)").is_err());
+        assert!(Expression::parse("(x").is_err());
+        assert!(Expression::parse("x $ y").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+pub mod expression;
+pub mod simple;
+pub use simple::ImplicitBuilder;
+use {
+    super::{pick_closest_intersection, Intersection, Shape},
+    crate::{Ray, TOLERANCE},
+    expression::Expression,
+    nalgebra::{Isometry3, Point2, Point3, Translation3, Unit, Vector3},
+};
+
+/// Number of bisection steps used to refine a bracketed root.
+const BISECTION_STEPS: usize = 64;
+
+/// Surface given by the zero set of a function f(x, y, z) = 0, in local coordinates centered on
+/// `origin`. The function is only considered inside the axis-aligned bounding box
+/// [`bounds_min`, `bounds_max`], also given in local coordinates. The normal is the gradient of
+/// the function, so "above" is the region where f > 0. Having no natural parametrization, the
+/// surface coordinates of a point are its local X and Y coordinates.
+pub struct ImplicitShape {
+    function: Expression,
+    pub origin: Point3<f64>,
+    pub bounds_min: Point3<f64>,
+    pub bounds_max: Point3<f64>,
+    pub steps: usize,
+    to_local: Isometry3<f64>,
+    to_global: Isometry3<f64>,
+}
+
+impl ImplicitShape {
+    pub fn new(
+        function: Expression,
+        origin: Point3<f64>,
+        bounds_min: Point3<f64>,
+        bounds_max: Point3<f64>,
+        steps: usize,
+    ) -> Self {
+        let to_global =
+            Isometry3::from_parts(Translation3::from(origin.coords), Default::default());
+        Self {
+            function,
+            origin,
+            bounds_min,
+            bounds_max,
+            steps: steps.max(1),
+            to_local: to_global.inverse(),
+            to_global,
+        }
+    }
+
+    /// Evaluate the function at a point given in local coordinates.
+    fn eval(&self, point: &Point3<f64>) -> f64 {
+        self.function.eval(point.x, point.y, point.z)
+    }
+
+    /// Slab test of a line against the bounding box. Returns the parameter range inside the box,
+    /// if any.
+    fn line_box_range(&self, origin: &Point3<f64>, direction: &Vector3<f64>) -> Option<(f64, f64)> {
+        let mut t_min = f64::NEG_INFINITY;
+        let mut t_max = f64::INFINITY;
+        for i in 0..3 {
+            if direction[i].abs() <= f64::EPSILON {
+                if origin[i] < self.bounds_min[i] || origin[i] > self.bounds_max[i] {
+                    return None;
+                }
+            } else {
+                let t0 = (self.bounds_min[i] - origin[i]) / direction[i];
+                let t1 = (self.bounds_max[i] - origin[i]) / direction[i];
+                t_min = t_min.max(t0.min(t1));
+                t_max = t_max.min(t0.max(t1));
+            }
+        }
+        if t_min <= t_max {
+            Some((t_min, t_max))
+        } else {
+            None
+        }
+    }
+
+    /// Refine a root of the function along a line, bracketed by parameters `a` and `b`.
+    fn bisect(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Vector3<f64>,
+        mut a: f64,
+        mut b: f64,
+    ) -> f64 {
+        let mut fa = self.eval(&(origin + a * direction));
+        for _ in 0..BISECTION_STEPS {
+            let m = 0.5 * (a + b);
+            let fm = self.eval(&(origin + m * direction));
+            if fm == 0.0 {
+                return m;
+            }
+            if fa.signum() == fm.signum() {
+                a = m;
+                fa = fm;
+            } else {
+                b = m;
+            }
+        }
+        0.5 * (a + b)
+    }
+
+    /// Find the intersections of a line (in local coordinates) with the surface, by sampling the
+    /// function at regular steps inside the bounding box and refining every sign change found.
+    /// Roots behind the line origin are not searched for.
+    fn line_intersection(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Vector3<f64>,
+    ) -> Vec<Point3<f64>> {
+        let (t_start, t_end) = match self.line_box_range(origin, direction) {
+            Some((t0, t1)) if t1 >= 0.0 => (t0.max(0.0), t1),
+            _ => return vec![],
+        };
+
+        let dt = (t_end - t_start) / self.steps as f64;
+        let mut intersections = Vec::new();
+        let mut t_previous = t_start;
+        let mut f_previous = self.eval(&(origin + t_start * direction));
+        for i in 1..=self.steps {
+            let t = t_start + i as f64 * dt;
+            let f = self.eval(&(origin + t * direction));
+            if f == 0.0 {
+                intersections.push(origin + t * direction);
+            } else if f_previous != 0.0 && f.signum() != f_previous.signum() {
+                let root = self.bisect(origin, direction, t_previous, t);
+                intersections.push(origin + root * direction);
+            }
+            t_previous = t;
+            f_previous = f;
+        }
+        intersections
+    }
+
+    /// Gradient of the function at a local point, via central finite differences.
+    fn gradient(&self, point: &Point3<f64>) -> Vector3<f64> {
+        let h = TOLERANCE * (1.0 + point.coords.amax());
+        let mut gradient = Vector3::zeros();
+        for i in 0..3 {
+            let mut offset = Vector3::zeros();
+            offset[i] = h;
+            gradient[i] = (self.eval(&(point + offset)) - self.eval(&(point - offset))) / (2.0 * h);
+        }
+        gradient
+    }
+
+    fn box_contains(&self, point: &Point3<f64>) -> bool {
+        (0..3).all(|i| {
+            self.bounds_min[i] - TOLERANCE <= point[i] && point[i] <= self.bounds_max[i] + TOLERANCE
+        })
+    }
+}
+
+impl Shape for ImplicitShape {
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
+        let origin: Point3<f64> = self.to_local() * ray.origin;
+        let direction: Vector3<f64> = self.to_local() * ray.direction;
+        let intersections: Vec<Intersection> = self
+            .line_intersection(&origin, &direction)
+            .into_iter()
+            .map(|p| {
+                Intersection::new(
+                    ray,
+                    self.to_global() * p,
+                    Unit::new_normalize(self.to_global() * self.gradient(&p)),
+                    Point2::new(p.x, p.y),
+                )
+            })
+            .collect();
+        pick_closest_intersection(intersections)
+    }
+    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
+        Unit::new_normalize(self.to_global() * self.gradient(&(self.to_local() * point)))
+    }
+    fn contains(&self, point: &Point3<f64>) -> bool {
+        let point: Point3<f64> = self.to_local() * point;
+        self.box_contains(&point) && self.eval(&point).abs() <= TOLERANCE
+    }
+    fn origin(&self) -> &Point3<f64> {
+        &self.origin
+    }
+    fn to_local(&self) -> &Isometry3<f64> {
+        &self.to_local
+    }
+    fn to_global(&self) -> &Isometry3<f64> {
+        &self.to_global
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{surface::tests::bounce_off, SOP, VOP};
+    use std::sync::Arc;
+
+    fn implicit_sphere(origin: Point3<f64>) -> ImplicitShape {
+        ImplicitShape::new(
+            Expression::parse("x^2 + y^2 + z^2 - 1").unwrap(),
+            origin,
+            Point3::new(-2.0, -2.0, -2.0),
+            Point3::new(2.0, 2.0, 2.0),
+            64,
+        )
+    }
+
+    fn downwards_ray(origin: Point3<f64>) -> Ray {
+        Ray {
+            origin,
+            direction: Vector3::new(0.0, 0.0, -1.0),
+            vop: Arc::new(VOP::new(1.0, [0.0; 3])),
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        }
+    }
+
+    #[test]
+    fn sphere_intersection() {
+        let shape = implicit_sphere(Point3::origin());
+        let p = shape
+            .intersection(&downwards_ray(Point3::new(0.0, 0.0, 10.0)))
+            .unwrap()
+            .point;
+        assert!((p - Point3::new(0.0, 0.0, 1.0)).norm() <= TOLERANCE);
+        assert!(shape.contains(&p));
+    }
+
+    #[test]
+    fn translated_sphere_intersection() {
+        let shape = implicit_sphere(Point3::new(1.0, 1.0, 0.0));
+        let p = shape
+            .intersection(&downwards_ray(Point3::new(1.0, 1.0, 10.0)))
+            .unwrap()
+            .point;
+        assert!((p - Point3::new(1.0, 1.0, 1.0)).norm() <= TOLERANCE);
+    }
+
+    #[test]
+    fn intersection_from_inside() {
+        let shape = implicit_sphere(Point3::origin());
+        let p = shape
+            .intersection(&downwards_ray(Point3::origin()))
+            .unwrap()
+            .point;
+        assert!((p - Point3::new(0.0, 0.0, -1.0)).norm() <= TOLERANCE);
+    }
+
+    #[test]
+    fn miss() {
+        let shape = implicit_sphere(Point3::origin());
+        assert!(shape
+            .intersection(&downwards_ray(Point3::new(1.5, 0.0, 10.0)))
+            .is_none());
+        assert!(shape
+            .intersection(&downwards_ray(Point3::new(0.0, 0.0, -10.0)))
+            .is_none());
+    }
+
+    #[test]
+    fn gradient_normal() {
+        let shape = implicit_sphere(Point3::origin());
+        let normal = shape.unchecked_normal_at(&Point3::new(0.0, 1.0, 0.0));
+        assert!((normal.into_inner() - Vector3::y()).norm() <= TOLERANCE);
+    }
+
+    /// Rays reflected off the surface at a grazing angle must not hit it again where they left
+    /// it, and rays refracted into it must next hit it across.
+    #[test]
+    fn grazing_bounces() {
+        let surface = |sop| super::simple::Implicit {
+            geometry: implicit_sphere(Point3::origin()),
+            sop,
+            vop_above: Arc::new(VOP::new(1.0, [0.0; 3])),
+            vop_below: Arc::new(VOP::new(1.5, [0.0; 3])),
+        };
+        let origin = Point3::new(-10.0, 0.99, 0.0);
+        let (_, next) = bounce_off(&surface(SOP::Reflect), origin, Vector3::x());
+        assert!(next.is_none());
+        let (point, next) = bounce_off(&surface(SOP::Refract), origin, Vector3::x());
+        assert!((next.unwrap() - point).norm() > 0.5);
+    }
+}
@@ -0,0 +1,76 @@
+use {
+    super::{
+        super::{Hit, Shape, Surface, SurfaceBuilder},
+        expression::Expression,
+        ImplicitShape,
+    },
+    crate::{Ray, SOP, VOP},
+    collections::HashMap,
+    nalgebra::Point3,
+    serde::Deserialize,
+    std::collections,
+    std::sync::Arc,
+};
+
+pub struct Implicit {
+    pub geometry: ImplicitShape,
+    pub sop: SOP,
+    pub vop_above: Arc<VOP>,
+    pub vop_below: Arc<VOP>,
+}
+
+#[derive(Deserialize)]
+pub struct ImplicitBuilder {
+    pub function: String,
+    #[serde(default = "default_origin")]
+    pub origin: [f64; 3],
+    pub bounds_min: [f64; 3],
+    pub bounds_max: [f64; 3],
+    #[serde(default = "default_steps")]
+    pub steps: usize,
+    pub sop: SOP,
+    pub vop_below: String,
+    pub vop_above: String,
+}
+
+fn default_origin() -> [f64; 3] {
+    [0.0, 0.0, 0.0]
+}
+
+fn default_steps() -> usize {
+    100
+}
+
+impl Surface for Implicit {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry
+            .intersection(ray)
+            .map(|i| Hit::new(i, ray, self.sop, &self.vop_above, &self.vop_below))
+    }
+}
+
+impl SurfaceBuilder for ImplicitBuilder {
+    fn build(self, vop_map: &HashMap<String, Arc<VOP>>) -> Arc<dyn Surface + Send + Sync> {
+        let function = Expression::parse(&self.function).unwrap_or_else(|e| {
+            panic!("Could not parse implicit function {}: {}", self.function, e)
+        });
+        Arc::new(Implicit {
+            geometry: ImplicitShape::new(
+                function,
+                Point3::from_slice(&self.origin),
+                Point3::from_slice(&self.bounds_min),
+                Point3::from_slice(&self.bounds_max),
+                self.steps,
+            ),
+            sop: self.sop,
+            vop_above: vop_map
+                .get(&self.vop_above)
+                .expect("No VOP above mapping found.")
+                .clone(),
+            vop_below: vop_map
+                .get(&self.vop_below)
+                .expect("No VOP above mapping found.")
+                .clone(),
+        })
+    }
+}
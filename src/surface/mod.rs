@@ -1,11 +1,15 @@
+pub mod bezier;
 pub mod cylinder;
 pub mod disk;
+pub mod implicit;
 pub mod paraboloid;
 pub mod plane;
 pub mod rectangle;
 pub mod sphere;
 pub use {
+    bezier::BezierBuilder,
     cylinder::CylinderBuilder,
+    implicit::ImplicitBuilder,
     paraboloid::ParaboloidBuilder,
     plane::{CheckerboardBuilder, MandelbrotPlaneBuilder, PlaneBuilder},
     rectangle::{RectangleBuilder, TexturedRectangleBuilder},
@@ -13,8 +17,11 @@ pub use {
 };
 
 use {
-    crate::{Ray, TOLERANCE, VOP},
-    nalgebra::{Isometry3, Point3, Unit, Vector3},
+    crate::{
+        packet::{RayPacket, PACKET_SIZE},
+        Ray, TOLERANCE, VOP,
+    },
+    nalgebra::{Isometry3, Point2, Point3, Unit, Vector3},
     serde::Deserialize,
     std::collections::HashMap,
     std::sync::Arc,
@@ -37,29 +44,74 @@ fn random_orthogonal(vector: &Vector3<f64>) -> Vector3<f64> {
     other.cross(vector)
 }
 
-/// Pick closest ray intersection out of all possible line intersections.
-pub fn pick_closest_intersection(
-    line_intersections: Vec<Point3<f64>>,
-    ray: &Ray,
-) -> Option<Point3<f64>> {
-    if line_intersections.is_empty() {
-        return None;
+/// Pick closest ray intersection out of all possible line intersections. Rays leaving a surface
+/// start off it, so every intersection ahead of the origin counts.
+pub fn pick_closest_intersection(line_intersections: Vec<Intersection>) -> Option<Intersection> {
+    line_intersections
+        .into_iter()
+        .filter(|i| i.t > 0.0)
+        .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())
+}
+
+/// Geometric part of a ray intersection with a shape, in global coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Intersection {
+    /// Ray parameter of the intersection, such that `point = origin + t * direction`.
+    pub t: f64,
+    pub point: Point3<f64>,
+    /// Normal of the shape at the intersection, pointing into the medium "above".
+    pub normal: Unit<Vector3<f64>>,
+    /// Surface coordinates of the intersection, as defined by each shape.
+    pub uv: Point2<f64>,
+}
+
+impl Intersection {
+    /// Create an intersection for a point known to lie on the ray's line.
+    pub fn new(ray: &Ray, point: Point3<f64>, normal: Unit<Vector3<f64>>, uv: Point2<f64>) -> Self {
+        Self {
+            t: (point - ray.origin).dot(&ray.direction) / ray.direction.norm_squared(),
+            point,
+            normal,
+            uv,
+        }
     }
-    let mut enumerated_dsq: Vec<(usize, f64)> = line_intersections
-        .iter()
-        .enumerate()
-        .map(|(i, p)| (i, *p - ray.origin))
-        .filter(|(_, d)| d.dot(&ray.direction) >= 0.0)
-        .map(|(i, d)| (i, d.norm_squared()))
-        .filter(|(_, d2)| *d2 >= TOLERANCE)
-        .collect();
-
-    match enumerated_dsq.len() {
-        0 => None,
-        1 => Some(line_intersections[enumerated_dsq[0].0]),
-        _ => {
-            enumerated_dsq.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
-            Some(line_intersections[enumerated_dsq[0].0])
+}
+
+/// Everything needed to bounce a ray off a surface, gathered in a single intersection call.
+#[derive(Debug, Clone)]
+pub struct Hit<'a> {
+    pub t: f64,
+    pub point: Point3<f64>,
+    /// Normal of the surface, pointing into the medium "above", regardless of the ray direction.
+    pub normal: Unit<Vector3<f64>>,
+    pub uv: Point2<f64>,
+    /// Whether the ray comes from the side into which the normal points.
+    pub front_face: bool,
+    pub sop: SOP,
+    pub vop_above: &'a Arc<VOP>,
+    pub vop_below: &'a Arc<VOP>,
+    /// Priority of the surface over overlapping ones for the medium it bounds.
+    pub priority: i32,
+}
+
+impl<'a> Hit<'a> {
+    pub fn new(
+        intersection: Intersection,
+        ray: &Ray,
+        sop: SOP,
+        vop_above: &'a Arc<VOP>,
+        vop_below: &'a Arc<VOP>,
+    ) -> Self {
+        Self {
+            t: intersection.t,
+            point: intersection.point,
+            normal: intersection.normal,
+            uv: intersection.uv,
+            front_face: intersection.normal.dot(&ray.direction) <= 0.0,
+            sop,
+            vop_above,
+            vop_below,
+            priority: 0,
         }
     }
 }
@@ -76,7 +128,7 @@ pub enum SOP {
 // TODO: SOP by loading image texture?
 
 pub trait Shape {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>>;
+    fn intersection(&self, ray: &Ray) -> Option<Intersection>;
     fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>>;
     fn contains(&self, point: &Point3<f64>) -> bool;
     fn intersects(&self, ray: &Ray) -> bool {
@@ -88,13 +140,64 @@ pub trait Shape {
 }
 
 pub trait Surface {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>>;
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>>;
-    fn unchecked_vop_above_at(&self, point: &Point3<f64>) -> Arc<VOP>;
-    fn unchecked_vop_below_at(&self, point: &Point3<f64>) -> Arc<VOP>;
-    fn unchecked_sop_at(&self, point: &Point3<f64>) -> SOP;
+    /// Closest intersection of a ray with the surface, with all the surface data at that point.
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>>;
+
+    /// Ray parameter of the closest intersection of every ray in a packet, infinite for misses.
+    /// By default each ray is intersected on its own; surfaces with a cheap closed-form
+    /// intersection test the whole packet at once.
+    fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        let mut distances = [f64::INFINITY; PACKET_SIZE];
+        for (distance, ray) in distances.iter_mut().zip(packet.rays.iter()) {
+            if let Some(hit) = self.intersection(ray) {
+                *distance = hit.t;
+            }
+        }
+        distances
+    }
+
+    /// Direction towards a point of the surface drawn with two uniform numbers, as seen from a
+    /// point at a given time, along with its density per unit solid angle. Only light sources
+    /// that can be sampled this way return one, for scattering media to gather their light.
+    fn sample_light(
+        &self,
+        _from: &Point3<f64>,
+        _time: f64,
+        _u: [f64; 2],
+    ) -> Option<(Vector3<f64>, f64)> {
+        None
+    }
 }
 
 pub trait SurfaceBuilder {
     fn build(self, vop_map: &HashMap<String, Arc<VOP>>) -> Arc<dyn Surface + Send + Sync>;
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Bounce a ray from air off a surface, giving the point it hits and the next point of the
+    /// same surface the bounced ray hits, if any.
+    pub(crate) fn bounce_off(
+        surface: &dyn Surface,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+    ) -> (Point3<f64>, Option<Point3<f64>>) {
+        let mut ray = Ray {
+            origin,
+            direction,
+            vop: Arc::new(VOP::new(1.0, [0.0; 3])),
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        };
+        let hit = surface
+            .intersection(&ray)
+            .expect("The ray must hit the surface.");
+        let point = hit.point;
+        ray.bounce(&hit);
+        (point, surface.intersection(&ray).map(|next| next.point))
+    }
+}
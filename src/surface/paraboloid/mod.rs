@@ -2,9 +2,9 @@ pub mod simple;
 use super::plane::PlaneShape;
 pub use simple::ParaboloidBuilder;
 use {
-    super::{pick_closest_intersection, Shape},
+    super::{pick_closest_intersection, Intersection, Shape},
     crate::{Ray, TOLERANCE},
-    nalgebra::{Isometry3, Point3, Unit, Vector3},
+    nalgebra::{Isometry3, Point2, Point3, Unit, Vector3},
 };
 // TODO: check asq and bsq are > 0
 pub struct ParaboloidShape {
@@ -62,17 +62,24 @@ impl ParaboloidShape {
 
         // no solution
         if delta <= 0.0 {
-            return vec![];
+            vec![]
         // single solution
         } else if delta <= f64::EPSILON {
-            return vec![*origin + alpha * *direction];
+            vec![*origin + alpha * *direction]
         } else {
-            return vec![
+            vec![
                 *origin + (alpha - delta.sqrt()) * *direction,
                 *origin + (alpha + delta.sqrt()) * *direction,
-            ];
+            ]
         }
     }
+
+    /// Normal at a point given in local coordinates, itself in local coordinates.
+    fn local_normal_at(&self, point: &Point3<f64>) -> Vector3<f64> {
+        let rx: Vector3<f64> = Vector3::new(1.0, 0.0, 2.0 * point.x / self.asq);
+        let ry: Vector3<f64> = Vector3::new(0.0, 1.0, 2.0 * point.y / self.bsq);
+        rx.cross(&ry)
+    }
 }
 
 impl Shape for ParaboloidShape {
@@ -83,18 +90,26 @@ impl Shape for ParaboloidShape {
     fn origin(&self) -> &Point3<f64> {
         &self.origin
     }
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
         let origin: Point3<f64> = self.to_local() * ray.origin;
         let direction: Vector3<f64> = self.to_local() * ray.direction;
-        let intersection =
-            pick_closest_intersection(self.line_intersection(&origin, &direction), ray);
-        intersection.map(|p| self.to_global() * p).to_owned()
+        let intersections: Vec<Intersection> = self
+            .line_intersection(&origin, &direction)
+            .into_iter()
+            .map(|p| {
+                Intersection::new(
+                    ray,
+                    self.to_global() * p,
+                    Unit::new_normalize(self.to_global() * self.local_normal_at(&p)),
+                    Point2::new(p.x, p.y),
+                )
+            })
+            .collect();
+        pick_closest_intersection(intersections)
     }
     fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
         let point: Point3<f64> = self.to_local() * point;
-        let rx: Vector3<f64> = Vector3::new(1.0, 0.0, 2.0 * point.x / self.asq);
-        let ry: Vector3<f64> = Vector3::new(0.0, 1.0, 2.0 * point.y / self.bsq);
-        Unit::new_normalize(self.to_global() * rx.cross(&ry))
+        Unit::new_normalize(self.to_global() * self.local_normal_at(&point))
     }
     fn to_local(&self) -> &Isometry3<f64> {
         self.plane.to_local()
@@ -107,8 +122,11 @@ impl Shape for ParaboloidShape {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::VOP;
-    use std::sync::Arc;
+    use crate::{
+        surface::{tests::bounce_off, SurfaceBuilder},
+        SOP, VOP,
+    };
+    use std::{collections::HashMap, sync::Arc};
 
     fn center_paraboloid() -> ParaboloidShape {
         ParaboloidShape::new(
@@ -137,19 +155,49 @@ mod tests {
     #[test]
     fn ray_intersections() {
         let p = center_paraboloid();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let r = Ray {
             origin: Point3::new(0.0, -10.0, 2.0),
             direction: Vector3::y(),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
 
         let intersections = p.line_intersection(&r.origin, &r.direction);
         assert!((intersections[0] - Point3::new(0.0, -(2.0f64.sqrt()), 2.0)).norm() <= TOLERANCE);
         assert!((intersections[1] - Point3::new(0.0, 2.0f64.sqrt(), 2.0)).norm() <= TOLERANCE);
     }
+
+    /// Rays reflected off the paraboloid at a grazing angle must not hit it again where they left
+    /// it, and rays refracted into it must next hit it across.
+    #[test]
+    fn grazing_bounces() {
+        let surface = |sop| {
+            let vops: HashMap<String, Arc<VOP>> = vec![
+                ("air".to_owned(), Arc::new(VOP::new(1.0, [0.0; 3]))),
+                ("glass".to_owned(), Arc::new(VOP::new(1.5, [0.0; 3]))),
+            ]
+            .into_iter()
+            .collect();
+            ParaboloidBuilder {
+                origin: [0.0; 3],
+                normal: [0.0, 0.0, 1.0],
+                orientation: [1.0, 0.0, 0.0],
+                asq: 1.0,
+                bsq: 1.0,
+                sop,
+                vop_above: "glass".to_owned(),
+                vop_below: "air".to_owned(),
+            }
+            .build(&vops)
+        };
+        let origin = Point3::new(0.999, -10.0, 1.0);
+        let (_, next) = bounce_off(surface(SOP::Reflect).as_ref(), origin, Vector3::y());
+        assert!(next.is_none());
+        let (point, next) = bounce_off(surface(SOP::Refract).as_ref(), origin, Vector3::y());
+        assert!((next.unwrap() - point).norm() > 0.5);
+    }
 }
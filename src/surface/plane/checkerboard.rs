@@ -1,11 +1,14 @@
 use {
     super::{
-        super::{Shape, Surface, SurfaceBuilder},
+        super::{Hit, Shape, Surface, SurfaceBuilder},
         PlaneShape,
     },
-    crate::{Ray, SOP, VOP},
+    crate::{
+        packet::{RayPacket, PACKET_SIZE},
+        Ray, SOP, VOP,
+    },
     collections::HashMap,
-    nalgebra::{Point3, Unit, Vector3},
+    nalgebra::{Point2, Point3, Vector3},
     serde::Deserialize,
     std::collections,
     std::sync::Arc,
@@ -14,7 +17,6 @@ use {
 pub struct Checkerboard {
     geometry: PlaneShape,
     sop: SOP,
-    orientation: Unit<Vector3<f64>>,
     tile_size: f64,
     vop_above: Arc<VOP>,
     vop_below: Arc<VOP>,
@@ -31,29 +33,11 @@ pub struct CheckerboardBuilder {
     pub vop_above: String,
 }
 
-impl Surface for Checkerboard {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        self.geometry.intersection(ray)
-    }
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
-        self.geometry.unchecked_normal_at(point)
-    }
-    fn unchecked_vop_above_at(&self, _: &Point3<f64>) -> Arc<VOP> {
-        self.vop_above.clone()
-    }
-    fn unchecked_vop_below_at(&self, _: &Point3<f64>) -> Arc<VOP> {
-        self.vop_below.clone()
-    }
-    fn unchecked_sop_at(&self, point: &Point3<f64>) -> SOP {
-        let y = self
-            .unchecked_normal_at(point)
-            .cross(&self.orientation)
-            .normalize();
-        let x = self.orientation;
-        let from_origin = *point - self.geometry.origin;
-
-        let size_x = from_origin.dot(&x) / self.tile_size;
-        let size_y = from_origin.dot(&y) / self.tile_size;
+impl Checkerboard {
+    /// Color of the tile at the given surface coordinates, in tile size units.
+    fn sop_at(&self, uv: &Point2<f64>) -> SOP {
+        let size_x = uv.x / self.tile_size;
+        let size_y = uv.y / self.tile_size;
         if (size_x.floor() as i64 + size_y.floor() as i64) % 2 == 0 {
             self.sop
         } else {
@@ -62,6 +46,18 @@ impl Surface for Checkerboard {
     }
 }
 
+impl Surface for Checkerboard {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry.intersection(ray).map(|i| {
+            let sop = self.sop_at(&i.uv);
+            Hit::new(i, ray, sop, &self.vop_above, &self.vop_below)
+        })
+    }
+    fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        self.geometry.packet_distances(packet)
+    }
+}
+
 impl SurfaceBuilder for CheckerboardBuilder {
     fn build(self, vop_map: &HashMap<String, Arc<VOP>>) -> Arc<dyn Surface + Send + Sync> {
         Arc::new(Checkerboard {
@@ -70,7 +66,6 @@ impl SurfaceBuilder for CheckerboardBuilder {
                 Vector3::from_row_slice(&self.normal),
                 Some(Vector3::from_row_slice(&self.orientation)),
             ),
-            orientation: Unit::new_normalize(Vector3::from_row_slice(&self.orientation)),
             sop: self.sop,
             tile_size: self.tile_size,
             vop_above: vop_map
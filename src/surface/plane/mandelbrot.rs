@@ -1,12 +1,16 @@
 use {
     super::{
-        super::{Shape, Surface, SurfaceBuilder},
+        super::{Hit, Shape, Surface, SurfaceBuilder},
         PlaneShape,
     },
-    crate::{colormap::load_colormap, Ray, SOP, VOP},
+    crate::{
+        colormap::load_colormap,
+        packet::{RayPacket, PACKET_SIZE},
+        Ray, SOP, VOP,
+    },
     collections::HashMap,
     colorgrad::Color,
-    nalgebra::{Point3, Unit, Vector3},
+    nalgebra::{Point2, Point3, Vector3},
     num_complex::Complex,
     serde::Deserialize,
     std::collections,
@@ -15,7 +19,6 @@ use {
 
 pub struct MandelbrotPlane {
     pub geometry: PlaneShape,
-    pub orientation: Unit<Vector3<f64>>,
     pub vop_above: Arc<VOP>,
     pub vop_below: Arc<VOP>,
     pub colormap: Vec<Color>,
@@ -77,7 +80,6 @@ impl SurfaceBuilder for MandelbrotPlaneBuilder {
                 Vector3::from_row_slice(&self.normal),
                 Some(Vector3::from_row_slice(&self.orientation)),
             ),
-            orientation: Unit::new_normalize(Vector3::from_row_slice(&self.orientation)),
             colormap: load_colormap(&self.colormap, self.num_colors),
             vop_above: vop_map
                 .get(&self.vop_above)
@@ -94,36 +96,12 @@ impl SurfaceBuilder for MandelbrotPlaneBuilder {
     }
 }
 
-impl Surface for MandelbrotPlane {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        self.geometry.intersection(ray)
-    }
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
-        self.geometry.unchecked_normal_at(point)
-    }
-    fn unchecked_vop_above_at(&self, _: &Point3<f64>) -> Arc<VOP> {
-        self.vop_above.clone()
-    }
-    fn unchecked_vop_below_at(&self, _: &Point3<f64>) -> Arc<VOP> {
-        self.vop_below.clone()
-    }
-    #[allow(clippy::clippy::many_single_char_names)]
-    fn unchecked_sop_at(&self, point: &Point3<f64>) -> SOP {
-        // intersection with plane
-        let y: Vector3<f64> = self
-            .unchecked_normal_at(point)
-            .cross(&self.orientation)
-            .normalize();
-        let x: Vector3<f64> = self.orientation.normalize(); // TODO: should be by default
-        let from_origin: Vector3<f64> = *point - self.geometry.origin;
-
-        let xproj = from_origin.dot(&x);
-        let yproj = from_origin.dot(&y);
+impl MandelbrotPlane {
+    /// Color of the Mandelbrot set at the given surface coordinates.
+    fn sop_at(&self, uv: &Point2<f64>) -> SOP {
         let x = mandelbrot(
-            xproj / self.mandelbrot_scale - self.mandelbrot_origin[0],
-            yproj / self.mandelbrot_scale - self.mandelbrot_origin[1],
-            // (xproj - self.mandelbrot_origin[0]) / self.mandelbrot_scale,
-            // (yproj - self.mandelbrot_origin[1]) / self.mandelbrot_scale,
+            uv.x / self.mandelbrot_scale - self.mandelbrot_origin[0],
+            uv.y / self.mandelbrot_scale - self.mandelbrot_origin[1],
             self.mandelbrot_maxiter,
         );
         if x == self.mandelbrot_maxiter {
@@ -138,3 +116,15 @@ impl Surface for MandelbrotPlane {
         }
     }
 }
+
+impl Surface for MandelbrotPlane {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry.intersection(ray).map(|i| {
+            let sop = self.sop_at(&i.uv);
+            Hit::new(i, ray, sop, &self.vop_above, &self.vop_below)
+        })
+    }
+    fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        self.geometry.packet_distances(packet)
+    }
+}
@@ -9,9 +9,12 @@ pub use {
 use crate::TOLERANCE;
 
 use {
-    super::{random_orthogonal, Shape},
-    crate::Ray,
-    nalgebra::{Point3, Unit, Vector3},
+    super::{random_orthogonal, Intersection, Shape},
+    crate::{
+        packet::{lane_dot, RayPacket, PACKET_SIZE},
+        Ray,
+    },
+    nalgebra::{Point2, Point3, Unit, Vector3},
 };
 
 // TODO: exception for *PERFECTLY* parallel case
@@ -50,12 +53,8 @@ pub fn plane_intersects_ray(
     if let Some(intersection) =
         plane_intersects_line(plane_origin, plane_normal, &ray.origin, &ray.direction)
     {
-        let origin_to_intersection = intersection - ray.origin;
-        // ray origin is not on plane
-        if !plane_contains_point(plane_origin, plane_normal, &ray.origin) &&
-            // ray is going towards plane
-            origin_to_intersection.dot(&ray.direction) >= 0.0
-        {
+        // ray is going towards plane
+        if (intersection - ray.origin).dot(&ray.direction) > 0.0 {
             return Some(intersection);
         }
     }
@@ -65,7 +64,7 @@ pub fn plane_intersects_ray(
 pub struct PlaneShape {
     pub origin: Point3<f64>,
     pub normal: Unit<Vector3<f64>>,
-    // orientation: Unit<Vector3<f64>>, // QUESTION: what about this?
+    pub orientation: Unit<Vector3<f64>>,
     to_local: Isometry3<f64>,
     to_global: Isometry3<f64>,
 }
@@ -87,16 +86,45 @@ impl PlaneShape {
         Self {
             origin,
             normal: Unit::new_normalize(normal),
-            // orientation,
+            orientation,
             to_local,
             to_global: to_local.inverse(),
         }
     }
+
+    /// Coordinates of a point of the plane along the orientation and along normal x orientation.
+    pub fn uv_at(&self, point: &Point3<f64>) -> Point2<f64> {
+        let from_origin = *point - self.origin;
+        Point2::new(
+            from_origin.dot(&self.orientation),
+            from_origin.dot(&self.normal.cross(&self.orientation)),
+        )
+    }
+
+    /// Packet version of the ray intersection, giving the ray parameter along each lane.
+    pub fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        let normal = [self.normal.x, self.normal.y, self.normal.z];
+        let normal_dot_direction = lane_dot(&packet.direction, &normal);
+        let normal_dot_origin = lane_dot(&packet.origin, &normal);
+        let normal_dot_plane = self.normal.dot(&self.origin.coords);
+
+        let mut distances = [f64::INFINITY; PACKET_SIZE];
+        for lane in 0..PACKET_SIZE {
+            let numerator = normal_dot_plane - normal_dot_origin[lane];
+            let t = numerator / normal_dot_direction[lane];
+            // line is not parallel to the plane and plane is ahead
+            if normal_dot_direction[lane].abs() > f64::EPSILON && t > 0.0 {
+                distances[lane] = t;
+            }
+        }
+        distances
+    }
 }
 
 impl Shape for PlaneShape {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
         plane_intersects_ray(&self.origin, &self.normal, ray)
+            .map(|p| Intersection::new(ray, p, self.normal, self.uv_at(&p)))
     }
     fn unchecked_normal_at(&self, _: &Point3<f64>) -> Unit<Vector3<f64>> {
         self.normal
@@ -136,33 +164,60 @@ mod tests {
     #[test]
     fn test_intersection() {
         let plane = xy_plane();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(0.0, 1.0, -1.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(plane.intersects(&ray));
-        assert_eq!(plane.intersection(&ray), Some(Point3::new(0.0, 1.0, 0.0)));
+        assert_eq!(
+            plane.intersection(&ray).map(|i| i.point),
+            Some(Point3::new(0.0, 1.0, 0.0))
+        );
     }
     #[test]
     fn test_no_intersection() {
         let plane = xy_plane();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(1.0, 0.0, 0.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(!plane.intersects(&ray));
-        assert_eq!(plane.intersection(&ray), None);
+        assert!(plane.intersection(&ray).is_none());
+    }
+
+    /// Rays starting very close to the plane must still hit it, and rays leaving it must not.
+    #[test]
+    fn grazing_intersection() {
+        let plane = xy_plane();
+        let mut ray = Ray {
+            origin: Point3::new(0.0, 0.0, 1e-6),
+            direction: Vector3::new(1.0, 0.0, -1e-3),
+            vop: Arc::new(VOP::new(1.0, [0.0; 3])),
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        };
+        let p = plane.intersection(&ray).unwrap().point;
+        assert!((p - Point3::new(1e-3, 0.0, 0.0)).norm() <= TOLERANCE);
+        let rays = vec![ray.clone(); PACKET_SIZE];
+        let packet = RayPacket::new(&rays);
+        assert!((plane.packet_distances(&packet)[0] - 1e-3).abs() <= TOLERANCE);
+
+        ray.origin = p;
+        ray.direction.z = 1e-3;
+        assert!(plane.intersection(&ray).is_none());
     }
 }
@@ -1,11 +1,14 @@
 use {
     super::{
-        super::{Shape, Surface, SurfaceBuilder},
+        super::{Hit, Shape, Surface, SurfaceBuilder},
         PlaneShape,
     },
-    crate::{Ray, SOP, VOP},
+    crate::{
+        packet::{RayPacket, PACKET_SIZE},
+        Ray, SOP, VOP,
+    },
     collections::HashMap,
-    nalgebra::{Point3, Unit, Vector3},
+    nalgebra::{Point3, Vector3},
     serde::Deserialize,
     std::collections,
     std::sync::Arc,
@@ -49,19 +52,12 @@ impl SurfaceBuilder for PlaneBuilder {
 }
 
 impl Surface for Plane {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        self.geometry.intersection(ray)
-    }
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
-        self.geometry.unchecked_normal_at(point)
-    }
-    fn unchecked_vop_above_at(&self, _: &Point3<f64>) -> Arc<VOP> {
-        self.vop_above.clone()
-    }
-    fn unchecked_vop_below_at(&self, _: &Point3<f64>) -> Arc<VOP> {
-        self.vop_below.clone()
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry
+            .intersection(ray)
+            .map(|i| Hit::new(i, ray, self.sop, &self.vop_above, &self.vop_below))
     }
-    fn unchecked_sop_at(&self, _: &Point3<f64>) -> SOP {
-        self.sop
+    fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        self.geometry.packet_distances(packet)
     }
 }
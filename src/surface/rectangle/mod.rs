@@ -2,7 +2,7 @@ pub mod simple;
 pub mod textured;
 use super::plane::PlaneShape;
 use {
-    super::Shape,
+    super::{Intersection, Shape},
     crate::ray::Ray,
     nalgebra::{Isometry3, Point3, Unit, Vector3},
 };
@@ -34,10 +34,11 @@ impl RectangleShape {
 }
 
 impl Shape for RectangleShape {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        if let Some(p) = self.plane.intersection(ray) {
-            if self.contains(&p) {
-                return Some(p);
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
+        if let Some(i) = self.plane.intersection(ray) {
+            // surface coordinates are along the orientation and its orthogonal, from the center
+            if i.uv.x.abs() <= self.size[0] / 2.0 && i.uv.y.abs() <= self.size[1] / 2.0 {
+                return Some(i);
             }
         }
         None
@@ -86,51 +87,54 @@ mod tests {
     #[test]
     fn test_intersection() {
         let square = xy_square();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(0.0, 1.0, -1.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(square.intersects(&ray));
-        assert_eq!(square.intersection(&ray), Some(Point3::new(0.0, 1.0, 0.0)));
+        assert_eq!(
+            square.intersection(&ray).map(|i| i.point),
+            Some(Point3::new(0.0, 1.0, 0.0))
+        );
     }
 
     #[test]
     fn test_intersection_by_missing() {
         let square = xy_square();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(0.0, 3.0, -1.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(!square.intersects(&ray));
-        assert_eq!(square.intersection(&ray), None);
+        assert!(square.intersection(&ray).is_none());
     }
 
     #[test]
     fn test_no_intersection_by_direction() {
         let square = xy_square();
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         let ray = Ray {
             origin: Point3::new(0.0, 0.0, 1.0),
             direction: Vector3::new(1.0, 0.0, 0.0),
             vop: air,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         };
         assert!(!square.intersects(&ray));
-        assert_eq!(square.intersection(&ray), None);
+        assert!(square.intersection(&ray).is_none());
     }
 }
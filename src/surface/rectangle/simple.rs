@@ -1,11 +1,11 @@
 use {
     super::{
-        super::{Shape, Surface, SurfaceBuilder},
+        super::{Hit, Shape, Surface, SurfaceBuilder},
         RectangleShape,
     },
     crate::{Ray, SOP, VOP},
     collections::HashMap,
-    nalgebra::{Point3, Unit, Vector3},
+    nalgebra::{Point3, Vector3},
     serde::Deserialize,
     std::collections,
     std::sync::Arc,
@@ -30,20 +30,10 @@ pub struct RectangleBuilder {
 }
 
 impl Surface for Rectangle {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        self.geometry.intersection(ray)
-    }
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
-        self.geometry.unchecked_normal_at(point)
-    }
-    fn unchecked_vop_above_at(&self, _point: &Point3<f64>) -> Arc<VOP> {
-        self.vop_above.clone()
-    }
-    fn unchecked_vop_below_at(&self, _point: &Point3<f64>) -> Arc<VOP> {
-        self.vop_below.clone()
-    }
-    fn unchecked_sop_at(&self, _point: &Point3<f64>) -> SOP {
-        self.sop
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry
+            .intersection(ray)
+            .map(|i| Hit::new(i, ray, self.sop, &self.vop_above, &self.vop_below))
     }
 }
 
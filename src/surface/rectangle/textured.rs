@@ -4,13 +4,13 @@ use crate::TOLERANCE;
 // TODO: fix orientation
 use {
     super::{
-        super::{Shape, Surface, SurfaceBuilder},
+        super::{Hit, Shape, Surface, SurfaceBuilder},
         RectangleShape,
     },
     crate::{Ray, SOP, VOP},
     collections::HashMap,
     image::{io::Reader, DynamicImage, RgbImage},
-    nalgebra::{Point3, Unit, Vector3},
+    nalgebra::{Point2, Point3, Vector3},
     serde::Deserialize,
     std::collections,
     std::sync::Arc,
@@ -35,37 +35,27 @@ pub struct TexturedRectangleBuilder {
     pub vop_above: String,
 }
 
-impl Surface for TexturedRectangle {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        self.geometry.intersection(ray)
-    }
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
-        self.geometry.unchecked_normal_at(point)
-    }
-    fn unchecked_vop_above_at(&self, _point: &Point3<f64>) -> Arc<VOP> {
-        self.vop_above.clone()
-    }
-    fn unchecked_vop_below_at(&self, _point: &Point3<f64>) -> Arc<VOP> {
-        self.vop_below.clone()
-    }
-    fn unchecked_sop_at(&self, point: &Point3<f64>) -> SOP {
+impl TexturedRectangle {
+    /// Color of the texture at the given surface coordinates, which are relative to the center.
+    fn sop_at(&self, uv: &Point2<f64>) -> SOP {
         // orientation == vertical of image / texture, along 1st size dimension
-        let right: Vector3<f64> = self.geometry.orientation.cross(&self.geometry.normal);
-        let upper_left_corner: Point3<f64> = self.geometry.origin
-            + self.geometry.size[0] / 2.0 * self.geometry.orientation.into_inner()
-            - self.geometry.size[1] / 2.0 * right;
-
-        // size of texture image
-
-        // calculate ox and oy in "pixel" values
-        let offset: Vector3<f64> = point - upper_left_corner;
-        let oy = (offset.dot(&self.geometry.orientation)).abs() * self.size_scaling[0];
-        let ox = (offset.dot(&right)).abs() * self.size_scaling[1];
+        // calculate ox and oy in "pixel" values, from the upper left corner
+        let oy = (uv.x - self.geometry.size[0] / 2.0).abs() * self.size_scaling[0];
+        let ox = (uv.y - self.geometry.size[1] / 2.0).abs() * self.size_scaling[1];
         let color = self.texture.get_pixel(ox as u32, oy as u32);
         SOP::Light(color[0], color[1], color[2])
     }
 }
 
+impl Surface for TexturedRectangle {
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry.intersection(ray).map(|i| {
+            let sop = self.sop_at(&i.uv);
+            Hit::new(i, ray, sop, &self.vop_above, &self.vop_below)
+        })
+    }
+}
+
 /// Load an image to be used as a texture.
 fn load_texture_from_file(filepath: &str) -> DynamicImage {
     Reader::open(filepath)
@@ -1,9 +1,13 @@
 pub mod simple;
 pub use simple::SphereBuilder;
 use {
-    super::{pick_closest_intersection, plane::PlaneShape, Shape},
-    crate::{Ray, TOLERANCE},
-    nalgebra::{Isometry3, Point3, Unit, Vector3},
+    super::{plane::PlaneShape, Intersection, Shape},
+    crate::{
+        packet::{RayPacket, PACKET_SIZE},
+        Ray, TOLERANCE,
+    },
+    nalgebra::{Isometry3, Point2, Point3, Unit, Vector3},
+    std::f64::consts::PI,
 };
 
 pub struct SphereShape {
@@ -13,7 +17,7 @@ pub struct SphereShape {
 }
 
 impl SphereShape {
-    fn new(
+    pub fn new(
         center: Point3<f64>,
         radius: f64,
         north: Option<Vector3<f64>>,
@@ -45,36 +49,92 @@ impl SphereShape {
         (alpha, delta)
     }
 
-    /// Intersection of ray line with sphere
+    /// Intersection of ray line with sphere, nearest point first along the line direction.
     fn line_intersection(
         &self,
         origin: &Point3<f64>,
         direction: &Vector3<f64>,
-    ) -> Vec<Point3<f64>> {
+    ) -> impl Iterator<Item = Point3<f64>> {
         let (alpha, delta) = self.intersection_components(origin, direction);
 
-        // no intersection
-        if delta < 0.0 {
-            vec![]
-        } else {
-            let dn = direction.normalize();
+        let (near, far) = if delta < 0.0 {
+            // no intersection
+            (None, None)
+        } else if delta.abs() <= f64::EPSILON {
             // single intersection
-            if delta.abs() <= f64::EPSILON {
-                vec![*origin + alpha * dn]
+            (Some(alpha), None)
+        } else {
             // two intersections
+            (Some(alpha - delta.sqrt()), Some(alpha + delta.sqrt()))
+        };
+
+        let origin = *origin;
+        let dn = direction.normalize();
+        near.into_iter().chain(far).map(move |d| origin + d * dn)
+    }
+}
+
+impl SphereShape {
+    /// Packet version of the ray intersection, giving the ray parameter along each lane.
+    pub fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        let center = [self.center.x, self.center.y, self.center.z];
+        let mut to_center = packet.origin;
+        for (axis, lanes) in to_center.iter_mut().enumerate() {
+            for o in lanes.iter_mut() {
+                *o -= center[axis];
+            }
+        }
+
+        let mut distances = [f64::INFINITY; PACKET_SIZE];
+        for lane in 0..PACKET_SIZE {
+            let d = [
+                packet.direction[0][lane],
+                packet.direction[1][lane],
+                packet.direction[2][lane],
+            ];
+            let oc = [to_center[0][lane], to_center[1][lane], to_center[2][lane]];
+            let length = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+
+            // same components as the scalar version, along the normalized direction
+            let alpha = -(d[0] * oc[0] + d[1] * oc[1] + d[2] * oc[2]) / length;
+            let delta = alpha * alpha - (oc[0] * oc[0] + oc[1] * oc[1] + oc[2] * oc[2])
+                + self.radius * self.radius;
+            let root = delta.max(0.0).sqrt();
+            let near = alpha - root;
+            let far = alpha + root;
+
+            // first point ahead of the ray origin, along the normalized direction
+            let distance = if near > 0.0 {
+                near
+            } else if far > 0.0 {
+                far
             } else {
-                vec![
-                    *origin + (alpha - delta.sqrt()) * dn,
-                    *origin + (alpha + delta.sqrt()) * dn,
-                ]
+                f64::INFINITY
+            };
+            if delta >= 0.0 {
+                distances[lane] = distance / length;
             }
         }
+        distances
+    }
+
+    /// Longitude and colatitude of a point on the sphere, both normalized to [0, 1], with
+    /// respect to the north pole and the Greenwich meridian.
+    fn uv_at(&self, point: &Point3<f64>) -> Point2<f64> {
+        let local: Point3<f64> = self.to_local() * point;
+        Point2::new(
+            local.y.atan2(local.x) / (2.0 * PI) + 0.5,
+            (local.z / self.radius).clamp(-1.0, 1.0).acos() / PI,
+        )
     }
 }
 
 impl Shape for SphereShape {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        pick_closest_intersection(self.line_intersection(&ray.origin, &ray.direction), ray)
+    fn intersection(&self, ray: &Ray) -> Option<Intersection> {
+        // points come nearest first, so the first one ahead of the ray is the closest
+        self.line_intersection(&ray.origin, &ray.direction)
+            .find(|p| (p - ray.origin).dot(&ray.direction) > 0.0)
+            .map(|p| Intersection::new(ray, p, self.unchecked_normal_at(&p), self.uv_at(&p)))
     }
     fn contains(&self, point: &Point3<f64>) -> bool {
         ((self.center - *point).norm() - self.radius).abs() <= TOLERANCE
@@ -96,7 +156,7 @@ impl Shape for SphereShape {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::VOP;
+    use crate::{Surface, SOP, VOP};
     use std::sync::Arc;
 
     fn center_unit_sphere() -> SphereShape {
@@ -109,6 +169,9 @@ mod tests {
             direction: Vector3::new(0.0, 0.0, -1.0),
             vop,
             abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
         }
     }
 
@@ -116,14 +179,16 @@ mod tests {
     fn no_line_intersection() {
         assert!(center_unit_sphere()
             .line_intersection(&Point3::new(0.0, 0.0, 2.0), &Vector3::new(0.0, 1.0, 0.0))
-            .is_empty());
+            .next()
+            .is_none());
     }
 
     #[test]
     fn line_intersection_single_point() {
         assert_eq!(
             center_unit_sphere()
-                .line_intersection(&Point3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 1.0, 0.0)),
+                .line_intersection(&Point3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 1.0, 0.0))
+                .collect::<Vec<_>>(),
             vec![Point3::new(0.0, 0.0, 1.0)]
         );
     }
@@ -134,29 +199,76 @@ mod tests {
 
         assert_eq!(
             center_unit_sphere()
-                .line_intersection(&Point3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 0.0, 1.0)),
+                .line_intersection(&Point3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 0.0, 1.0))
+                .collect::<Vec<_>>(),
             result
         );
         result.reverse();
 
         assert_eq!(
             center_unit_sphere()
-                .line_intersection(&Point3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 0.0, -1.0)),
+                .line_intersection(&Point3::new(0.0, 0.0, 1.0), &Vector3::new(0.0, 0.0, -1.0))
+                .collect::<Vec<_>>(),
             result
         );
     }
 
     #[test]
     fn ray_intersection() {
-        let air = Arc::new(VOP {
-            ior: 1.0,
-            abs: [0.0; 3],
-        });
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
         assert_eq!(
             center_unit_sphere()
                 .intersection(&downwards_ray(air))
-                .unwrap(),
+                .unwrap()
+                .point,
             Point3::new(0.0, 0.0, 1.0)
         );
     }
+
+    /// Rays leaving a sphere far from the origin, where rounding errors are largest, must not hit
+    /// it again where they left it, even at a grazing angle.
+    #[test]
+    fn no_self_intersection_far_away() {
+        let glass = Arc::new(VOP::new(1.5, [0.0; 3]));
+        let air = Arc::new(VOP::new(1.0, [0.0; 3]));
+        let shape = || SphereShape::new(Point3::new(1e5, 0.0, 0.0), 1e3, None, None);
+        let mirror = super::simple::Sphere {
+            geometry: shape(),
+            sop: SOP::Reflect,
+            vop_above: air.clone(),
+            vop_below: air.clone(),
+        };
+        let mut ray = Ray {
+            origin: Point3::new(9e4, 999.9, 0.0),
+            direction: Vector3::x(),
+            vop: air.clone(),
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        };
+        let hit = mirror.intersection(&ray).unwrap();
+        ray.bounce(&hit);
+        assert!(mirror.intersection(&ray).is_none());
+
+        // refracted into the sphere, the next hit is across it
+        let lens = super::simple::Sphere {
+            geometry: shape(),
+            sop: SOP::Refract,
+            vop_above: air.clone(),
+            vop_below: glass,
+        };
+        let mut ray = Ray {
+            origin: Point3::new(9e4, 999.9, 0.0),
+            direction: Vector3::x(),
+            vop: air,
+            abs: [0.0; 3],
+            time: 0.0,
+            media: Default::default(),
+            path: Default::default(),
+        };
+        let hit = lens.intersection(&ray).unwrap();
+        ray.bounce(&hit);
+        assert!(lens.intersection(&ray).unwrap().t > 1.0);
+    }
 }
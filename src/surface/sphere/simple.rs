@@ -1,13 +1,18 @@
 use {
     super::{
-        super::{Shape, Surface, SurfaceBuilder},
+        super::{Hit, Shape, Surface, SurfaceBuilder},
         SphereShape,
     },
-    crate::{Ray, SOP, VOP},
+    crate::{
+        packet::{RayPacket, PACKET_SIZE},
+        scattering::basis,
+        Ray, SOP, VOP,
+    },
     collections::HashMap,
-    nalgebra::{Point3, Unit, Vector3},
+    nalgebra::{Point3, Vector3},
     serde::Deserialize,
     std::collections,
+    std::f64::consts::PI,
     std::sync::Arc,
 };
 
@@ -28,20 +33,36 @@ pub struct SphereBuilder {
 }
 
 impl Surface for Sphere {
-    fn intersection(&self, ray: &Ray) -> Option<Point3<f64>> {
-        self.geometry.intersection(ray)
-    }
-    fn unchecked_normal_at(&self, point: &Point3<f64>) -> Unit<Vector3<f64>> {
-        self.geometry.unchecked_normal_at(point)
-    }
-    fn unchecked_vop_above_at(&self, _point: &Point3<f64>) -> Arc<VOP> {
-        self.vop_above.clone()
+    fn intersection(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.geometry
+            .intersection(ray)
+            .map(|i| Hit::new(i, ray, self.sop, &self.vop_above, &self.vop_below))
     }
-    fn unchecked_vop_below_at(&self, _point: &Point3<f64>) -> Arc<VOP> {
-        self.vop_below.clone()
+    fn packet_distances(&self, packet: &RayPacket) -> [f64; PACKET_SIZE] {
+        self.geometry.packet_distances(packet)
     }
-    fn unchecked_sop_at(&self, _point: &Point3<f64>) -> SOP {
-        self.sop
+    fn sample_light(
+        &self,
+        from: &Point3<f64>,
+        _time: f64,
+        u: [f64; 2],
+    ) -> Option<(Vector3<f64>, f64)> {
+        if !matches!(self.sop, SOP::Light(..)) {
+            return None;
+        }
+        // uniform direction within the cone the sphere fills, seen from outside it
+        let to_center = self.geometry.center - from;
+        let sin_sq_max = (self.geometry.radius / to_center.norm()).powi(2);
+        if sin_sq_max >= 1.0 {
+            return None;
+        }
+        let cos_max = (1.0 - sin_sq_max).sqrt();
+        let cos_angle = 1.0 - u[0] * (1.0 - cos_max);
+        let sin_angle = (1.0 - cos_angle * cos_angle).max(0.0).sqrt();
+        let phi = 2.0 * PI * u[1];
+        let (w, a, b) = basis(&to_center);
+        let direction = cos_angle * w + sin_angle * (phi.cos() * a + phi.sin() * b);
+        Some((direction, 1.0 / (2.0 * PI * (1.0 - cos_max))))
     }
 }
 
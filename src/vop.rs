@@ -1,7 +1,28 @@
-use serde::Deserialize;
+use {crate::scattering::Phase, serde::Deserialize};
 
 #[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
 pub struct VOP {
     pub ior: f64,
     pub abs: [f64; 3],
+    /// RGB scattering coefficients per distance, light scattering out of the ray at that rate.
+    #[serde(default)]
+    pub scattering: [f64; 3],
+    #[serde(default)]
+    pub phase: Phase,
+}
+
+impl VOP {
+    /// Volume that only refracts and absorbs light, without scattering it.
+    pub fn new(ior: f64, abs: [f64; 3]) -> Self {
+        Self {
+            ior,
+            abs,
+            scattering: [0.0; 3],
+            phase: Phase::default(),
+        }
+    }
+
+    pub fn scatters(&self) -> bool {
+        self.scattering.iter().any(|&s| s > 0.0)
+    }
 }